@@ -0,0 +1,142 @@
+use crate::loader::RonAsset;
+use bevy::reflect::TypeUuid;
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+
+// A cast ability loaded from an `.ability.ron` file under `assets/abilities/`.
+//
+// Casting takes `cast_time` seconds and uses up a charge, charges come
+// back one at a time every `cooldown` seconds. When the cast finishes
+// every effect is applied in order at the targeted point, which is
+// pulled in to be at most `range` away from the caster.
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "8d3b3c35-7a54-4a4e-9d1c-5f0b7f2c6a41"]
+pub struct AbilityDef {
+    pub cast_time: f32,
+    pub cooldown: f32,
+    pub charges: i64,
+    pub range: f32,
+    #[serde(default)]
+    pub energy_cost: i64,
+    // Whether the caster keeps walking to the targeted point once the
+    // cast is done, otherwise it stops.
+    #[serde(default)]
+    pub move_after_cast: bool,
+    // Played when the cast finishes, relative to the assets folder.
+    #[serde(default)]
+    pub sound: Option<String>,
+    pub effects: Vec<EffectDef>,
+}
+
+// A channel ability loaded from a `.channel.ron` file under
+// `assets/abilities/`.
+//
+// While the channel goes on `energy_cost` energy is drained every
+// `drain_interval` seconds, the channel ends once the caster can't pay.
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "ea4c2041-e535-4f65-9c06-95792a9bf8fc"]
+pub struct ChannelDef {
+    pub energy_cost: i64,
+    pub drain_interval: f32,
+}
+
+// One effect of an ability, handled by the effect handler registered
+// under `name`. What the params mean is up to the handler.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EffectDef {
+    pub name: String,
+    #[serde(default)]
+    pub params: FxHashMap<String, f32>,
+}
+
+impl EffectDef {
+    pub fn param(&self, name: &str, default: f32) -> f32 {
+        self.params.get(name).copied().unwrap_or(default)
+    }
+}
+
+// Whether a number of seconds or distance from a file is usable, timers
+// panic on negative and non finite durations.
+fn non_negative(value: f32) -> bool {
+    value.is_finite() && value >= 0.0
+}
+
+impl RonAsset for AbilityDef {
+    const EXTENSIONS: &'static [&'static str] = &["ability.ron"];
+
+    fn validate(&self) -> Result<(), anyhow::Error> {
+        if !non_negative(self.cast_time) || !non_negative(self.range) {
+            anyhow::bail!("cast_time and range can't be negative");
+        }
+        if !non_negative(self.cooldown) || self.cooldown == 0.0 {
+            anyhow::bail!("cooldown must be positive, got {}", self.cooldown);
+        }
+        if self.charges < 1 {
+            anyhow::bail!("an ability needs at least one charge, got {}", self.charges);
+        }
+        if self.energy_cost < 0 {
+            anyhow::bail!("energy_cost can't be negative, got {}", self.energy_cost);
+        }
+        for effect in self.effects.iter() {
+            if let Some((name, value)) = effect
+                .params
+                .iter()
+                .find(|(_, value)| !non_negative(**value))
+            {
+                anyhow::bail!(
+                    "param \"{}\" of the \"{}\" effect can't be negative, got {}",
+                    name,
+                    effect.name,
+                    value
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RonAsset for ChannelDef {
+    const EXTENSIONS: &'static [&'static str] = &["channel.ron"];
+
+    fn validate(&self) -> Result<(), anyhow::Error> {
+        if !non_negative(self.drain_interval) || self.drain_interval == 0.0 {
+            anyhow::bail!("drain_interval must be positive");
+        }
+        if self.energy_cost < 0 {
+            anyhow::bail!("energy_cost can't be negative, got {}", self.energy_cost);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shot() -> AbilityDef {
+        ron::de::from_str(include_str!("../assets/abilities/shoot.ability.ron")).unwrap()
+    }
+
+    #[test]
+    fn shipped_ability_is_valid() {
+        assert!(shot().validate().is_ok());
+    }
+
+    #[test]
+    fn cooldown_that_is_not_a_number_is_rejected() {
+        let mut def = shot();
+        def.cooldown = f32::NAN;
+        assert!(def.validate().is_err());
+    }
+
+    #[test]
+    fn negative_effect_params_are_rejected() {
+        let mut def = shot();
+        def.effects[0].params.insert("duration".to_string(), -1.0);
+        assert!(def.validate().is_err());
+        def.effects[0]
+            .params
+            .insert("duration".to_string(), f32::INFINITY);
+        assert!(def.validate().is_err());
+    }
+}
@@ -1,34 +1,138 @@
 use crate::events::{Action, PlayerAction};
 use bevy::prelude::*;
 use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
 
 // Label components
 pub struct Player;
 pub struct Mob;
 pub struct Projectile;
 pub struct MainCamera;
-pub struct Dash;
+pub struct Lazer;
+pub struct LazerBeam;
 pub struct Cell;
 pub struct Impassable;
+pub struct WaypointMarker;
+
+// HUD label components
+pub struct HealthBar;
+pub struct HealthText;
+pub struct EnergyBar;
+pub struct EnergyText;
+
+// Index of a dash charge battery, lit while the player has more charges.
+pub struct ChargeBattery(pub i64);
+
+// Overlay on an ability slot that shrinks as the ability recharges.
+pub struct CooldownSweep(pub CastAbility);
+
+// Keybinding menu label components
+pub struct KeybindMenuRoot;
+pub struct BindingRow(pub Action);
+pub struct BindingRowText(pub Action);
+pub struct MenuMessage;
 
 // Player and mob components
 pub struct Health(pub i64);
+pub struct MaxHealth(pub i64);
 pub struct Energy(pub i64);
+pub struct MaxEnergy(pub i64);
+
+// Energy gained every time the timer ticks over.
+pub struct EnergyRegen {
+    pub amount: i64,
+    pub timer: Timer,
+}
 pub struct Experience(pub i64);
 pub struct MovementSpeed(pub f32);
+pub struct SpawnPoint(pub Vec3);
+pub struct RespawnTimer(pub Timer);
+
+// Where the last two simulation ticks left a unit, projectile or beam.
+// The simulation moves its transform, in between ticks it is drawn part
+// way from the previous position to the current one.
+pub struct SimPosition {
+    pub previous: Vec3,
+    pub current: Vec3,
+}
+
+impl SimPosition {
+    pub fn new(position: Vec3) -> Self {
+        Self {
+            previous: position,
+            current: position,
+        }
+    }
+
+    // For units that jump to a new position within a tick, so they are
+    // drawn there right away instead of sliding over.
+    pub fn teleport(&mut self, position: Vec3) {
+        self.previous = position;
+        self.current = position;
+    }
+}
+
+// Mob AI settings. Mobs chase the player once they come within the
+// aggro radius and walk back to their spawn point (ignoring the player)
+// whenever they are pulled further than the leash distance from it.
+pub struct MobBrain {
+    pub aggro_radius: f32,
+    pub leash_distance: f32,
+    pub attack_range: f32,
+    pub attack_damage: i64,
+    pub leashing: bool,
+}
+
+// Wind-up time of a mob attack.
+pub struct AttackTimer(pub Timer);
+
+// Commands the unit carries out in order once it is idle.
+#[derive(Default)]
+pub struct CommandQueue(pub std::collections::VecDeque<PlayerAction>);
 
 // Ability components
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum CastAbility {
     Dash,
     Shoot,
 }
 
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum ChannelAbility {
     Lazer,
 }
 
+// How a cast ability is aimed once its input is pressed. Every player
+// picks it per ability in their controls, see `UserControls::cast_mode`.
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum CastMode {
+    // Cast right away at the cursor.
+    Quick,
+    // Show the range while the input is held and cast at the
+    // cursor when it is released.
+    OnRelease,
+    // Show the range until the target is picked with the
+    // confirm action, left click by default.
+    Normal,
+}
+
+// How far from the caster an ability can reach.
+pub struct CastRange(pub f32);
+
+// Movement speed bonus from a buff effect, `base_speed` is put
+// back once the timer runs out.
+pub struct SpeedBuff {
+    pub base_speed: f32,
+    pub timer: Timer,
+}
+
+// Circle around the player showing the range of the ability
+// that is being aimed.
+pub struct RangeIndicator(pub CastAbility);
+
+// The unit an ability, or something spawned by one, belongs to.
+pub struct AbilityOwner(pub Entity);
+
 pub struct Cooldown(pub f32);
 pub struct Charges(pub i64);
 pub struct MaxCharges(pub i64);
@@ -36,9 +140,20 @@ pub struct CastTime(pub f32);
 pub struct CastTimer(pub Timer);
 pub struct CooldownTimer(pub Timer);
 
+// Channel components. Energy is drained every time the channel
+// timer ticks over and the sound timer restarts the looping sound.
+pub struct ChannelTimer(pub Timer);
+pub struct SoundTimer(pub Timer);
+
 #[derive(PartialEq, Clone, Copy)]
 pub struct Coords(pub Vec3);
 
+// Projectile components
+pub struct Velocity(pub Vec3);
+pub struct MaxRange(pub f32);
+pub struct DistanceTraveled(pub f32);
+pub struct Damage(pub i64);
+
 #[derive(PartialEq)]
 pub enum CharState {
     Casting((CastAbility, Coords)),
@@ -49,7 +164,13 @@ pub enum CharState {
     // move-tile coords.
     Moving(Coords, Option<std::collections::VecDeque<(i32, i32, i32)>>),
     Channeling((ChannelAbility, Coords)),
+
+    // Winding up an attack against the target unit.
+    Attacking(Entity),
     Idle,
+
+    // Dead units can't do anything until they respawn.
+    Dead,
 }
 
 impl CharState {
@@ -58,7 +179,9 @@ impl CharState {
             Self::Casting(_) => false,
             Self::Moving(..) => true,
             Self::Channeling(_) => true,
+            Self::Attacking(_) => false,
             Self::Idle => true,
+            Self::Dead => false,
         }
     }
 
@@ -67,7 +190,9 @@ impl CharState {
             Self::Casting(_) => false,
             Self::Moving(..) => true,
             Self::Channeling(_) => false,
+            Self::Attacking(_) => false,
             Self::Idle => true,
+            Self::Dead => false,
         }
     }
 }
@@ -80,26 +205,162 @@ impl From<PlayerAction> for CharState {
             Action::Channel(ability) => {
                 CharState::Channeling((ability, Coords(action.mouse_coords)))
             }
+            // confirming a target only picks where the aimed ability is
+            // cast, see `player_action_system`
+            Action::Confirm => CharState::Idle,
+        }
+    }
+}
+
+// When a binding sends its action.
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Trigger {
+    // When the input is pressed and once more when it is released.
+    Press,
+    // Once when the input is released.
+    Release,
+    // When the input is pressed, every frame while it is held
+    // and once more when it is released.
+    Held,
+}
+
+impl Trigger {
+    // Move and channels follow the input while it is held,
+    // casts go off once.
+    pub fn default_for(action: Action) -> Self {
+        match action {
+            Action::Move => Trigger::Held,
+            Action::Cast(_) => Trigger::Press,
+            Action::Channel(_) => Trigger::Held,
+            Action::Confirm => Trigger::Press,
+        }
+    }
+
+    // Channels go on until their input is released, so they need a
+    // trigger that sends the release.
+    pub fn allowed_for(self, action: Action) -> bool {
+        !matches!((action, self), (Action::Channel(_), Trigger::Release))
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Binding {
+    pub action: Action,
+    pub trigger: Trigger,
+}
+
+impl Binding {
+    pub fn new(action: Action) -> Self {
+        Self {
+            action,
+            trigger: Trigger::default_for(action),
         }
     }
 }
 
-// Map keyboard and mouse into player actions
+// Seconds a move or cast pressed during a cast is buffered for when
+// the user hasn't configured it.
+pub const DEFAULT_INPUT_BUFFER: f32 = 0.2;
+
+// Map keyboard, mouse and gamepad buttons into player actions. Loaded
+// from the user's config file at startup, see `config::controls_or_default`.
+// The gamepad sticks aren't bound, the left stick always moves the player
+// and the right stick aims.
 pub struct UserControls {
-    pub mouse: FxHashMap<MouseButton, Action>,
-    pub keyboard: FxHashMap<KeyCode, Action>,
+    pub mouse: FxHashMap<MouseButton, Binding>,
+    pub keyboard: FxHashMap<KeyCode, Binding>,
+    pub gamepad: FxHashMap<GamepadButtonType, Binding>,
+
+    // Seconds that a move or cast pressed during a cast is kept around
+    // to be used once the cast is done.
+    pub input_buffer: f32,
+
+    // Abilities that aren't quick cast.
+    pub cast_modes: FxHashMap<CastAbility, CastMode>,
 }
 
 impl UserControls {
     pub fn new() -> Self {
         let mut mouse = FxHashMap::default();
         let mut keyboard = FxHashMap::default();
+        let mut gamepad = FxHashMap::default();
+
+        mouse.insert(MouseButton::Right, Binding::new(Action::Move));
+        mouse.insert(MouseButton::Left, Binding::new(Action::Confirm));
+        keyboard.insert(KeyCode::Q, Binding::new(Action::Cast(CastAbility::Dash)));
+        keyboard.insert(KeyCode::W, Binding::new(Action::Cast(CastAbility::Shoot)));
+        keyboard.insert(
+            KeyCode::E,
+            Binding::new(Action::Channel(ChannelAbility::Lazer)),
+        );
+        gamepad.insert(
+            GamepadButtonType::South,
+            Binding::new(Action::Cast(CastAbility::Dash)),
+        );
+        gamepad.insert(
+            GamepadButtonType::RightTrigger2,
+            Binding::new(Action::Cast(CastAbility::Shoot)),
+        );
+        gamepad.insert(
+            GamepadButtonType::LeftTrigger2,
+            Binding::new(Action::Channel(ChannelAbility::Lazer)),
+        );
+
+        Self {
+            mouse,
+            keyboard,
+            gamepad,
+            input_buffer: DEFAULT_INPUT_BUFFER,
+            cast_modes: FxHashMap::default(),
+        }
+    }
 
-        mouse.insert(MouseButton::Right, Action::Move);
-        keyboard.insert(KeyCode::Q, Action::Cast(CastAbility::Dash));
-        keyboard.insert(KeyCode::W, Action::Cast(CastAbility::Shoot));
-        keyboard.insert(KeyCode::E, Action::Channel(ChannelAbility::Lazer));
+    pub fn cast_mode(&self, ability: CastAbility) -> CastMode {
+        self.cast_modes
+            .get(&ability)
+            .copied()
+            .unwrap_or(CastMode::Quick)
+    }
+
+    // Names of every button and key bound to `action`.
+    pub fn bindings(&self, action: Action) -> Vec<String> {
+        let mouse = self
+            .mouse
+            .iter()
+            .filter(|(_, bound)| bound.action == action)
+            .map(|(button, _)| format!("Mouse {:?}", button));
+        let keyboard = self
+            .keyboard
+            .iter()
+            .filter(|(_, bound)| bound.action == action)
+            .map(|(key, _)| format!("{:?}", key));
+        let gamepad = self
+            .gamepad
+            .iter()
+            .filter(|(_, bound)| bound.action == action)
+            .map(|(button, _)| format!("Pad {:?}", button));
+        mouse.chain(keyboard).chain(gamepad).collect()
+    }
+
+    // The trigger of the first binding of `action`, used to keep the
+    // trigger when the action is bound to a different input.
+    pub fn trigger(&self, action: Action) -> Trigger {
+        self.mouse
+            .values()
+            .chain(self.keyboard.values())
+            .chain(self.gamepad.values())
+            .find(|bound| bound.action == action)
+            .map_or(Trigger::default_for(action), |bound| bound.trigger)
+    }
+
+    // Removes every mouse and keyboard binding of `action`.
+    pub fn unbind_mouse_and_keyboard(&mut self, action: Action) {
+        self.mouse.retain(|_, bound| bound.action != action);
+        self.keyboard.retain(|_, bound| bound.action != action);
+    }
 
-        Self { mouse, keyboard }
+    // Removes every gamepad binding of `action`.
+    pub fn unbind_gamepad(&mut self, action: Action) {
+        self.gamepad.retain(|_, bound| bound.action != action);
     }
 }
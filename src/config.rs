@@ -0,0 +1,403 @@
+use crate::components::{Binding, CastAbility, CastMode, Trigger, UserControls};
+use crate::events::Action;
+use bevy::prelude::*;
+use rustc_hash::FxHashMap;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+
+// Key bindings as they are stored in the user's config file, for example
+//
+// ```ron
+// (
+//     bindings: [
+//         (input: Mouse("Right"), action: Move),
+//         (input: Key("Q"), action: Cast(Dash)),
+//         (input: Key("E"), action: Channel(Lazer), trigger: Some(Press)),
+//         (input: Gamepad("South"), action: Cast(Dash)),
+//     ],
+//     input_buffer: 0.2,
+//     cast_modes: {
+//         Dash: OnRelease,
+//     },
+// )
+// ```
+//
+// Inputs are named after their `MouseButton`, `KeyCode` and
+// `GamepadButtonType` variants.
+// Bindings without a trigger get the default trigger of their action,
+// see `Trigger::default_for`. Abilities without a cast mode are quick cast.
+#[derive(Serialize, Deserialize)]
+pub struct ControlsConfig {
+    pub bindings: Vec<BindingConfig>,
+    // Seconds, see `UserControls::input_buffer`.
+    #[serde(default = "default_input_buffer")]
+    pub input_buffer: f32,
+    #[serde(default)]
+    pub cast_modes: FxHashMap<CastAbility, CastMode>,
+}
+
+fn default_input_buffer() -> f32 {
+    UserControls::new().input_buffer
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BindingConfig {
+    pub input: InputName,
+    pub action: Action,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trigger: Option<Trigger>,
+}
+
+impl BindingConfig {
+    fn new(input: InputName, binding: &Binding) -> Self {
+        // only triggers that differ from the default are written out
+        let trigger = if binding.trigger == Trigger::default_for(binding.action) {
+            None
+        } else {
+            Some(binding.trigger)
+        };
+        Self {
+            input,
+            action: binding.action,
+            trigger,
+        }
+    }
+
+    fn binding(&self) -> Binding {
+        Binding {
+            action: self.action,
+            trigger: self
+                .trigger
+                .unwrap_or_else(|| Trigger::default_for(self.action)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum InputName {
+    Mouse(String),
+    Key(String),
+    Gamepad(String),
+}
+
+#[derive(Debug)]
+pub enum ControlsError {
+    Io(std::io::Error),
+    Parse(ron::Error),
+    UnknownMouseButton(String),
+    UnknownKey(String),
+    UnknownGamepadButton(String),
+    // The action can't be triggered that way, see `Trigger::allowed_for`.
+    InvalidTrigger {
+        input: InputName,
+        action: Action,
+        trigger: Trigger,
+    },
+    // The same input is bound to more than one action.
+    Conflict {
+        input: InputName,
+        first: Action,
+        second: Action,
+    },
+}
+
+impl fmt::Display for ControlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "could not access the controls file: {}", err),
+            Self::Parse(err) => write!(f, "could not parse the controls file: {}", err),
+            Self::UnknownMouseButton(name) => write!(f, "unknown mouse button \"{}\"", name),
+            Self::UnknownKey(name) => write!(f, "unknown key \"{}\"", name),
+            Self::UnknownGamepadButton(name) => {
+                write!(f, "unknown gamepad button \"{}\"", name)
+            }
+            Self::InvalidTrigger {
+                input,
+                action,
+                trigger,
+            } => write!(f, "{:?} can't trigger {:?} on {:?}", input, action, trigger),
+            Self::Conflict {
+                input,
+                first,
+                second,
+            } => write!(
+                f,
+                "{:?} is bound to both {:?} and {:?}",
+                input, first, second
+            ),
+        }
+    }
+}
+
+impl From<&UserControls> for ControlsConfig {
+    fn from(controls: &UserControls) -> Self {
+        let mouse = controls.mouse.iter().map(|(button, binding)| {
+            BindingConfig::new(InputName::Mouse(format!("{:?}", button)), binding)
+        });
+        let keyboard = controls.keyboard.iter().map(|(key, binding)| {
+            BindingConfig::new(InputName::Key(format!("{:?}", key)), binding)
+        });
+        let gamepad = controls.gamepad.iter().map(|(button, binding)| {
+            BindingConfig::new(InputName::Gamepad(format!("{:?}", button)), binding)
+        });
+        Self {
+            bindings: mouse.chain(keyboard).chain(gamepad).collect(),
+            input_buffer: controls.input_buffer,
+            cast_modes: controls.cast_modes.clone(),
+        }
+    }
+}
+
+impl ControlsConfig {
+    // Checks every binding and builds the controls out of them.
+    // Returns every problem that was found if any binding is invalid.
+    pub fn to_controls(&self) -> Result<UserControls, Vec<ControlsError>> {
+        let mut errors = Vec::new();
+        let mut mouse = FxHashMap::default();
+        let mut keyboard = FxHashMap::default();
+        let mut gamepad = FxHashMap::default();
+
+        for binding in self.bindings.iter() {
+            let trigger = binding.binding().trigger;
+            if !trigger.allowed_for(binding.action) {
+                errors.push(ControlsError::InvalidTrigger {
+                    input: binding.input.clone(),
+                    action: binding.action,
+                    trigger,
+                });
+                continue;
+            }
+
+            let previous = match &binding.input {
+                InputName::Mouse(name) => match input_from_name::<MouseButton>(name) {
+                    Some(button) => mouse.insert(button, binding.binding()),
+                    None => {
+                        errors.push(ControlsError::UnknownMouseButton(name.clone()));
+                        continue;
+                    }
+                },
+                InputName::Key(name) => match input_from_name::<KeyCode>(name) {
+                    Some(key) => keyboard.insert(key, binding.binding()),
+                    None => {
+                        errors.push(ControlsError::UnknownKey(name.clone()));
+                        continue;
+                    }
+                },
+                InputName::Gamepad(name) => match input_from_name::<GamepadButtonType>(name) {
+                    Some(button) => gamepad.insert(button, binding.binding()),
+                    None => {
+                        errors.push(ControlsError::UnknownGamepadButton(name.clone()));
+                        continue;
+                    }
+                },
+            };
+
+            if let Some(first) = previous {
+                errors.push(ControlsError::Conflict {
+                    input: binding.input.clone(),
+                    first: first.action,
+                    second: binding.action,
+                });
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(UserControls {
+                mouse,
+                keyboard,
+                gamepad,
+                input_buffer: self.input_buffer.max(0.0),
+                cast_modes: self.cast_modes.clone(),
+            })
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+// Where the controls are saved, `<config dir>/rust_game/controls.ron`.
+pub fn controls_path() -> Option<PathBuf> {
+    let config_dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| {
+            PathBuf::from(home)
+                .join("Library")
+                .join("Application Support")
+        })
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    config_dir.map(|dir| dir.join("rust_game").join("controls.ron"))
+}
+
+pub fn load_controls() -> Result<Option<UserControls>, Vec<ControlsError>> {
+    let path = match controls_path() {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(vec![ControlsError::Io(err)]),
+    };
+    let config: ControlsConfig =
+        ron::de::from_str(&contents).map_err(|err| vec![ControlsError::Parse(err)])?;
+    config.to_controls().map(Some)
+}
+
+pub fn save_controls(controls: &UserControls) -> Result<(), ControlsError> {
+    let path = match controls_path() {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let config = ControlsConfig::from(controls);
+    let contents = ron::ser::to_string_pretty(&config, ron::ser::PrettyConfig::default())
+        .map_err(ControlsError::Parse)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(ControlsError::Io)?;
+    }
+    std::fs::write(path, contents).map_err(ControlsError::Io)
+}
+
+// Controls from the config file. When there is no config file yet the
+// default controls are written there so they can be edited. When the
+// config file is invalid every problem is logged and the defaults are used.
+pub fn controls_or_default() -> UserControls {
+    match load_controls() {
+        Ok(Some(controls)) => controls,
+        Ok(None) => {
+            let controls = UserControls::new();
+            if let Err(err) = save_controls(&controls) {
+                warn!("{}", err);
+            }
+            controls
+        }
+        Err(errors) => {
+            for err in errors {
+                error!("{}", err);
+            }
+            warn!("using the default controls");
+            UserControls::new()
+        }
+    }
+}
+
+// Inputs are named after their variants, which is also how bevy
+// serializes them, so the names always match bevy's enums.
+fn input_from_name<T: DeserializeOwned>(name: &str) -> Option<T> {
+    ron::de::from_str(name).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::ChannelAbility;
+
+    fn config(bindings: Vec<(InputName, Action)>) -> ControlsConfig {
+        ControlsConfig {
+            bindings: bindings
+                .into_iter()
+                .map(|(input, action)| BindingConfig {
+                    input,
+                    action,
+                    trigger: None,
+                })
+                .collect(),
+            input_buffer: 0.2,
+            cast_modes: FxHashMap::default(),
+        }
+    }
+
+    #[test]
+    fn default_controls_round_trip() {
+        let defaults = UserControls::new();
+        let controls = ControlsConfig::from(&defaults).to_controls().unwrap();
+        assert_eq!(controls.mouse, defaults.mouse);
+        assert_eq!(controls.keyboard, defaults.keyboard);
+        assert_eq!(controls.gamepad, defaults.gamepad);
+    }
+
+    #[test]
+    fn inputs_are_found_by_their_variant_names() {
+        let controls = config(vec![
+            (InputName::Mouse("Other(4)".to_string()), Action::Move),
+            (InputName::Key("Numpad7".to_string()), Action::Confirm),
+            (InputName::Gamepad("DPadLeft".to_string()), Action::Confirm),
+        ])
+        .to_controls()
+        .unwrap();
+        assert!(controls.mouse.contains_key(&MouseButton::Other(4)));
+        assert!(controls.keyboard.contains_key(&KeyCode::Numpad7));
+        assert!(controls.gamepad.contains_key(&GamepadButtonType::DPadLeft));
+    }
+
+    #[test]
+    fn unknown_inputs_are_reported() {
+        let errors = config(vec![
+            (InputName::Mouse("Thumb".to_string()), Action::Move),
+            (InputName::Key("Qq".to_string()), Action::Confirm),
+            (
+                InputName::Key("Q".to_string()),
+                Action::Cast(CastAbility::Dash),
+            ),
+            (InputName::Gamepad("Jump".to_string()), Action::Confirm),
+        ])
+        .to_controls()
+        .unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert!(matches!(&errors[0], ControlsError::UnknownMouseButton(name) if name == "Thumb"));
+        assert!(matches!(&errors[1], ControlsError::UnknownKey(name) if name == "Qq"));
+        assert!(matches!(&errors[2], ControlsError::UnknownGamepadButton(name) if name == "Jump"));
+    }
+
+    #[test]
+    fn channels_cannot_trigger_on_release() {
+        let mut config = config(vec![(
+            InputName::Key("E".to_string()),
+            Action::Channel(ChannelAbility::Lazer),
+        )]);
+        config.bindings[0].trigger = Some(Trigger::Release);
+        let errors = config.to_controls().unwrap_err();
+        assert!(matches!(
+            &errors[..],
+            [ControlsError::InvalidTrigger {
+                trigger: Trigger::Release,
+                ..
+            }]
+        ));
+
+        config.bindings[0].trigger = Some(Trigger::Press);
+        assert!(config.to_controls().is_ok());
+    }
+
+    #[test]
+    fn inputs_bound_twice_conflict() {
+        let errors = config(vec![
+            (
+                InputName::Key("Q".to_string()),
+                Action::Cast(CastAbility::Dash),
+            ),
+            (
+                InputName::Key("W".to_string()),
+                Action::Cast(CastAbility::Shoot),
+            ),
+            (InputName::Key("Q".to_string()), Action::Confirm),
+        ])
+        .to_controls()
+        .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            &errors[0],
+            ControlsError::Conflict {
+                input: InputName::Key(name),
+                first: Action::Cast(CastAbility::Dash),
+                second: Action::Confirm,
+            } if name == "Q"
+        ));
+    }
+}
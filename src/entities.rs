@@ -1,14 +1,24 @@
+use crate::ability::{AbilityDef, ChannelDef};
 use crate::components::*;
+use crate::events::Action;
 use bevy::prelude::*;
+use bevy_prototype_lyon::entity::ShapeBundle;
+use bevy_prototype_lyon::prelude::*;
 
 #[derive(Bundle)]
 pub struct PlayerBundle {
     player: Player,
     health: Health,
+    max_health: MaxHealth,
     energy: Energy,
+    max_energy: MaxEnergy,
+    energy_regen: EnergyRegen,
     experience: Experience,
     movement_speed: MovementSpeed,
+    spawn_point: SpawnPoint,
     player_state: CharState,
+    command_queue: CommandQueue,
+    sim_position: SimPosition,
     #[bundle]
     sprite: SpriteBundle,
 }
@@ -17,18 +27,29 @@ impl PlayerBundle {
     pub fn new(
         assest_server: &Res<AssetServer>,
         materials: &mut ResMut<Assets<ColorMaterial>>,
+        spawn_point: Vec3,
     ) -> Self {
         let texture_handle = assest_server.load("player.png");
         Self {
             player: Player,
             health: Health(100),
+            max_health: MaxHealth(100),
             energy: Energy(100),
+            max_energy: MaxEnergy(100),
+            // 10 energy per second
+            energy_regen: EnergyRegen {
+                amount: 1,
+                timer: Timer::from_seconds(0.1, true),
+            },
             experience: Experience(0),
             movement_speed: MovementSpeed(300.0),
+            spawn_point: SpawnPoint(spawn_point),
             player_state: CharState::Idle,
+            command_queue: CommandQueue::default(),
+            sim_position: SimPosition::new(spawn_point),
             sprite: SpriteBundle {
                 material: materials.add(texture_handle.into()),
-                transform: Transform::from_xyz(0.0, -50.0, 1.0),
+                transform: Transform::from_translation(spawn_point),
                 sprite: Sprite::new(Vec2::new(160.0, 120.0)),
                 ..Default::default()
             },
@@ -40,9 +61,16 @@ impl PlayerBundle {
 pub struct MobBundle {
     mob: Mob,
     health: Health,
+    max_health: MaxHealth,
     energy: Energy,
+    max_energy: MaxEnergy,
+    energy_regen: EnergyRegen,
     movement_speed: MovementSpeed,
+    spawn_point: SpawnPoint,
+    brain: MobBrain,
+    attack_timer: AttackTimer,
     mob_state: CharState,
+    sim_position: SimPosition,
     #[bundle]
     sprite: SpriteBundle,
 }
@@ -51,17 +79,35 @@ impl MobBundle {
     pub fn new(
         assest_server: &Res<AssetServer>,
         materials: &mut ResMut<Assets<ColorMaterial>>,
+        spawn_point: Vec3,
     ) -> Self {
         let texture_handle = assest_server.load("eye.png");
         Self {
             mob: Mob,
             health: Health(100),
+            max_health: MaxHealth(100),
             energy: Energy(100),
+            max_energy: MaxEnergy(100),
+            // 10 energy per second
+            energy_regen: EnergyRegen {
+                amount: 1,
+                timer: Timer::from_seconds(0.1, true),
+            },
             movement_speed: MovementSpeed(300.0),
+            spawn_point: SpawnPoint(spawn_point),
+            brain: MobBrain {
+                aggro_radius: 300.0,
+                leash_distance: 600.0,
+                attack_range: 80.0,
+                attack_damage: 10,
+                leashing: false,
+            },
+            attack_timer: AttackTimer(Timer::from_seconds(0.6, false)),
             mob_state: CharState::Idle,
+            sim_position: SimPosition::new(spawn_point),
             sprite: SpriteBundle {
                 material: materials.add(texture_handle.into()),
-                transform: Transform::from_xyz(100.0, 100.0, 1.0),
+                transform: Transform::from_translation(spawn_point),
                 sprite: Sprite::new(Vec2::new(100.0, 100.0)),
                 ..Default::default()
             },
@@ -70,9 +116,11 @@ impl MobBundle {
 }
 
 #[derive(Bundle)]
-pub struct DashBundle {
-    ability: Dash,
+pub struct AbilityBundle {
+    owner: AbilityOwner,
     ability_class: CastAbility,
+    def: Handle<AbilityDef>,
+    cast_range: CastRange,
     cooldown: Cooldown,
     cooldown_timer: CooldownTimer,
     charges: Charges,
@@ -81,30 +129,208 @@ pub struct DashBundle {
     cast_timer: CastTimer,
 }
 
-impl DashBundle {
-    pub fn new() -> Self {
-        let ability = Dash;
-        let ability_class = CastAbility::Dash;
-        let cooldown = Cooldown(3.0);
-        let cast_time = CastTime(0.1);
-        let charges = Charges(3);
-        let max_charges = MaxCharges(3);
-
-        let mut cooldown_timer = CooldownTimer(Timer::from_seconds(cooldown.0, true));
+impl AbilityBundle {
+    // The ability has no charges until `def` has loaded and
+    // `abilities::ability_def_system` fills in the rest.
+    pub fn new(owner: Entity, ability_class: CastAbility, def: Handle<AbilityDef>) -> Self {
+        let mut cooldown_timer = CooldownTimer(Timer::from_seconds(1.0, true));
         cooldown_timer.0.pause();
 
-        let mut cast_timer = CastTimer(Timer::from_seconds(cast_time.0, false));
+        // set up from the cast time whenever a cast starts
+        let mut cast_timer = CastTimer(Timer::from_seconds(0.0, false));
         cast_timer.0.pause();
 
         Self {
-            ability,
+            owner: AbilityOwner(owner),
             ability_class,
-            cooldown,
+            def,
+            cast_range: CastRange(0.0),
+            cooldown: Cooldown(1.0),
             cooldown_timer,
-            charges,
-            max_charges,
-            cast_time,
+            charges: Charges(0),
+            max_charges: MaxCharges(0),
+            cast_time: CastTime(0.0),
             cast_timer,
         }
     }
 }
+
+#[derive(Bundle)]
+pub struct ProjectileBundle {
+    projectile: Projectile,
+    owner: AbilityOwner,
+    velocity: Velocity,
+    max_range: MaxRange,
+    distance_traveled: DistanceTraveled,
+    damage: Damage,
+    sim_position: SimPosition,
+    #[bundle]
+    shape: ShapeBundle,
+}
+
+impl ProjectileBundle {
+    // Projectile fired by `owner` starting at `origin` and flying towards
+    // `target`. The projectile keeps going past the target until it hits
+    // a unit other than its owner or reaches max range.
+    pub fn new(
+        owner: Entity,
+        origin: Vec3,
+        target: Vec3,
+        speed: f32,
+        range: f32,
+        damage: i64,
+    ) -> Self {
+        // a projectile that doesn't move would never despawn, so one
+        // fired at its own origin flies right instead
+        let offset = Vec3::new(target.x - origin.x, target.y - origin.y, 0.0);
+        let direction = if offset.length_squared() > 0.0 {
+            offset.normalize()
+        } else {
+            Vec3::X
+        };
+        let shape = shapes::Circle {
+            radius: 6.0,
+            ..shapes::Circle::default()
+        };
+        let position = Vec3::new(origin.x, origin.y, 2.0);
+
+        Self {
+            projectile: Projectile,
+            owner: AbilityOwner(owner),
+            velocity: Velocity(speed * direction),
+            max_range: MaxRange(range),
+            distance_traveled: DistanceTraveled(0.0),
+            damage: Damage(damage),
+            sim_position: SimPosition::new(position),
+            shape: GeometryBuilder::build_as(
+                &shape,
+                ShapeColors::outlined(Color::ORANGE_RED, Color::BLACK),
+                DrawMode::Outlined {
+                    fill_options: FillOptions::default(),
+                    outline_options: StrokeOptions::default().with_line_width(1.0),
+                },
+                Transform::from_translation(position),
+            ),
+        }
+    }
+}
+
+#[derive(Bundle)]
+pub struct LazerBundle {
+    ability: Lazer,
+    owner: AbilityOwner,
+    ability_class: ChannelAbility,
+    def: Handle<ChannelDef>,
+    channel_timer: ChannelTimer,
+    sound_timer: SoundTimer,
+}
+
+impl LazerBundle {
+    pub fn new(owner: Entity, def: Handle<ChannelDef>) -> Self {
+        Self {
+            ability: Lazer,
+            owner: AbilityOwner(owner),
+            ability_class: ChannelAbility::Lazer,
+            def,
+            // set up from the definition whenever a channel starts
+            channel_timer: ChannelTimer(Timer::from_seconds(1.0, true)),
+            // lazer.wav is a short clip so it gets replayed while channeling
+            sound_timer: SoundTimer(Timer::from_seconds(0.125, true)),
+        }
+    }
+}
+
+#[derive(Bundle)]
+pub struct BeamBundle {
+    beam: LazerBeam,
+    owner: AbilityOwner,
+    sim_position: SimPosition,
+    #[bundle]
+    shape: ShapeBundle,
+}
+
+impl BeamBundle {
+    pub fn new(owner: Entity, start: Vec3, end: Vec3) -> Self {
+        // unit length line that gets stretched and rotated by its transform
+        let line = shapes::Line(Vec2::ZERO, Vec2::X);
+        let transform = BeamBundle::transform(start, end);
+        Self {
+            beam: LazerBeam,
+            owner: AbilityOwner(owner),
+            // drawn from its owner as it is interpolated between ticks
+            sim_position: SimPosition::new(transform.translation),
+            shape: GeometryBuilder::build_as(
+                &line,
+                ShapeColors::new(Color::CYAN),
+                DrawMode::Stroke(StrokeOptions::default().with_line_width(4.0)),
+                transform,
+            ),
+        }
+    }
+
+    // Transform that stretches the unit beam from `start` to `end`.
+    pub fn transform(start: Vec3, end: Vec3) -> Transform {
+        let direction = Vec2::new(end.x - start.x, end.y - start.y);
+        Transform {
+            translation: Vec3::new(start.x, start.y, 2.0),
+            rotation: Quat::from_rotation_z(direction.y.atan2(direction.x)),
+            scale: Vec3::new(direction.length(), 1.0, 1.0),
+        }
+    }
+}
+
+#[derive(Bundle)]
+pub struct RangeIndicatorBundle {
+    indicator: RangeIndicator,
+    #[bundle]
+    shape: ShapeBundle,
+}
+
+impl RangeIndicatorBundle {
+    pub fn new(ability: CastAbility, range: f32, center: Vec3) -> Self {
+        let circle = shapes::Circle {
+            radius: range,
+            ..shapes::Circle::default()
+        };
+        Self {
+            indicator: RangeIndicator(ability),
+            shape: GeometryBuilder::build_as(
+                &circle,
+                ShapeColors::new(Color::rgba(1.0, 1.0, 1.0, 0.4)),
+                DrawMode::Stroke(StrokeOptions::default().with_line_width(2.0)),
+                Transform::from_xyz(center.x, center.y, 2.0),
+            ),
+        }
+    }
+}
+
+#[derive(Bundle)]
+pub struct WaypointMarkerBundle {
+    marker: WaypointMarker,
+    #[bundle]
+    shape: ShapeBundle,
+}
+
+impl WaypointMarkerBundle {
+    // Hexagon outline inside the tile centered at `center`.
+    pub fn new(action: Action, cell_size: f32, center: Vec3) -> Self {
+        let hexagon = shapes::RegularPolygon {
+            sides: 6,
+            feature: shapes::RegularPolygonFeature::Radius(cell_size * 0.7),
+            ..shapes::RegularPolygon::default()
+        };
+        let color = match action {
+            Action::Move => Color::LIME_GREEN,
+            _ => Color::ORANGE,
+        };
+        Self {
+            marker: WaypointMarker,
+            shape: GeometryBuilder::build_as(
+                &hexagon,
+                ShapeColors::new(color),
+                DrawMode::Stroke(StrokeOptions::default().with_line_width(2.0)),
+                Transform::from_xyz(center.x, center.y, 0.5),
+            ),
+        }
+    }
+}
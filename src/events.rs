@@ -1,24 +1,96 @@
+use crate::ability::EffectDef;
 use crate::components::*;
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct PlayerAction {
     pub action: Action,
+    pub phase: ActionPhase,
     pub mouse_coords: Vec3,
+    // Sent while shift was held, queued after the player's current commands.
+    pub queued: bool,
 }
 
-#[derive(PartialEq, Clone, Copy)]
+// Where in a press of its input an action was sent. Bindings that
+// trigger on release only ever send `Started` and bindings that
+// trigger on press never send `Held`.
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum ActionPhase {
+    Started,
+    Held,
+    Ended,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum Action {
     Move,
     Cast(CastAbility),
     Channel(ChannelAbility),
+    // Picks the target of the ability being aimed.
+    Confirm,
+}
+
+impl Action {
+    // Every action that can be bound to an input.
+    pub const ALL: [Action; 5] = [
+        Action::Move,
+        Action::Cast(CastAbility::Dash),
+        Action::Cast(CastAbility::Shoot),
+        Action::Channel(ChannelAbility::Lazer),
+        Action::Confirm,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Move => "Move",
+            Action::Cast(CastAbility::Dash) => "Dash",
+            Action::Cast(CastAbility::Shoot) => "Shoot",
+            Action::Channel(ChannelAbility::Lazer) => "Lazer",
+            Action::Confirm => "Confirm",
+        }
+    }
 }
 
 impl PlayerAction {
-    pub fn new(action: Action, mouse_coords: Vec3) -> Self {
+    pub fn new(action: Action, phase: ActionPhase, mouse_coords: Vec3) -> Self {
         Self {
             action,
+            phase,
             mouse_coords,
+            queued: false,
         }
     }
 }
+
+// Combat events. Anything that wants to change the health of a unit
+// sends one of these instead of touching `Health` directly.
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: i64,
+}
+
+pub struct HealEvent {
+    pub target: Entity,
+    pub amount: i64,
+}
+
+// Sent once when a unit's health reaches zero.
+pub struct DeathEvent {
+    pub entity: Entity,
+}
+
+// Sent when a unit tries to use an ability without enough energy,
+// or runs out of energy while channeling.
+pub struct NotEnoughEnergyEvent {
+    pub unit: Entity,
+}
+
+// Sent for each effect of an ability when its cast finishes and picked
+// up by the effect handler registered under the effect's name.
+pub struct AbilityEffectEvent {
+    pub caster: Entity,
+    pub effect: EffectDef,
+    pub origin: Vec3,
+    pub target: Vec3,
+}
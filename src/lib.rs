@@ -0,0 +1,12 @@
+pub mod ability;
+pub mod components;
+pub mod config;
+pub mod entities;
+pub mod events;
+pub mod loader;
+pub mod map;
+pub mod plugins;
+pub mod replay;
+pub mod resources;
+pub mod schedule;
+pub mod systems;
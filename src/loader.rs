@@ -0,0 +1,42 @@
+use bevy::asset::{Asset, AssetLoader, LoadContext, LoadedAsset};
+use bevy::utils::BoxedFuture;
+use serde::de::DeserializeOwned;
+use std::marker::PhantomData;
+
+// An asset that is stored in a RON file and checked once it is parsed.
+pub trait RonAsset: Asset + DeserializeOwned {
+    // Extensions of the asset's files, for example "map.ron".
+    const EXTENSIONS: &'static [&'static str];
+
+    // Whether the parsed asset makes sense, files that don't fail to load
+    // instead of causing trouble once they are used.
+    fn validate(&self) -> Result<(), anyhow::Error>;
+}
+
+// Loads the files of any `RonAsset`.
+pub struct RonLoader<T>(PhantomData<fn() -> T>);
+
+impl<T> Default for RonLoader<T> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T: RonAsset> AssetLoader for RonLoader<T> {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let asset: T = ron::de::from_bytes(bytes)?;
+            asset.validate()?;
+            load_context.set_default_asset(LoadedAsset::new(asset));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        T::EXTENSIONS
+    }
+}
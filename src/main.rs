@@ -1,24 +1,49 @@
-mod components;
-mod entities;
-mod events;
-mod systems;
-
-use crate::events::*;
-use crate::systems::*;
 use bevy::prelude::*;
 use bevy_prototype_lyon::prelude::*;
+use rust_game::plugins::*;
+use rust_game::replay::{Recorder, Replay};
+
+const USAGE: &str = "usage: rust_game [--record <file>] [--replay <file>]";
 
 fn main() {
-    App::build()
-        .insert_resource(Msaa { samples: 8 })
+    let mut record = None;
+    let mut replay = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => record = Some(args.next().unwrap_or_else(|| exit_with("missing file"))),
+            "--replay" => replay = Some(args.next().unwrap_or_else(|| exit_with("missing file"))),
+            _ => exit_with(&format!("unknown argument \"{}\"", arg)),
+        }
+    }
+
+    let mut app = App::build();
+    app.insert_resource(Msaa { samples: 8 })
         .add_plugins(DefaultPlugins)
         .add_plugin(ShapePlugin)
-        .add_startup_system(setup::setup_system.system())
-        .add_system(player_action::player_action_system.system())
-        .add_system(input::input_system.system())
-        .add_system(movement::movement_system.system())
-        .add_system(abilities::dash.system())
-        .add_system(abilities::charges_cooldown_system.system())
-        .add_event::<PlayerAction>()
-        .run();
+        .add_plugin(GameplayPlugin)
+        .add_plugin(MapPlugin)
+        .add_plugin(HudPlugin);
+
+    // a replay drives the player instead of the mouse, keyboard and gamepad
+    match replay {
+        Some(path) => match Replay::load(&path) {
+            Ok(replay) => app.insert_resource(replay).add_plugin(ReplayPlugin),
+            Err(err) => exit_with(&format!("{}: {}", path, err)),
+        },
+        None => app.add_plugin(InputPlugin),
+    };
+    if let Some(path) = record {
+        match Recorder::create(&path) {
+            Ok(recorder) => app.insert_resource(recorder).add_plugin(RecordPlugin),
+            Err(err) => exit_with(&format!("{}: {}", path, err)),
+        };
+    }
+
+    app.run();
+}
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    std::process::exit(1);
 }
@@ -0,0 +1,88 @@
+use crate::loader::RonAsset;
+use bevy::asset::Handle;
+use bevy::reflect::TypeUuid;
+use serde::Deserialize;
+
+// A hex arena loaded from a `.map.ron` file under `assets/maps/`.
+//
+// All tiles are given in cube coordinates (x, y, z) where
+// x + y + z == 0. See `TileGraph` for more on the coordinate systems.
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "2ff1c1e9-351d-4f18-8d3e-cbd77f27672c"]
+pub struct MapAsset {
+    // Length from center to vertex of each hexagon tile.
+    pub cell_size: f32,
+    pub shape: MapShape,
+    // Tiles that are drawn but can't be walked through or dashed into.
+    #[serde(default)]
+    pub impassable: Vec<(i32, i32, i32)>,
+    pub player_spawn: (i32, i32, i32),
+    #[serde(default)]
+    pub mob_spawns: Vec<(i32, i32, i32)>,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum MapShape {
+    // Full hexagon with `radius` rings of tiles around the center tile.
+    Hexagon(i32),
+    // Exactly the listed tiles.
+    Tiles(Vec<(i32, i32, i32)>),
+}
+
+impl MapShape {
+    // Every tile in the shape.
+    pub fn tiles(&self) -> Vec<(i32, i32, i32)> {
+        match self {
+            MapShape::Hexagon(radius) => {
+                let radius = *radius;
+                (-radius..=radius)
+                    .flat_map(|x| (-radius..=radius).map(move |y| (x, y, -x - y)))
+                    .filter(|(_, _, z)| (-radius..=radius).contains(z))
+                    .collect()
+            }
+            MapShape::Tiles(tiles) => tiles.clone(),
+        }
+    }
+}
+
+impl MapAsset {
+    // Every tile on the map, including the impassable ones.
+    pub fn tiles(&self) -> Vec<(i32, i32, i32)> {
+        self.shape.tiles()
+    }
+
+    // Tiles that units can stand on.
+    pub fn passable_tiles(&self) -> Vec<(i32, i32, i32)> {
+        self.tiles()
+            .into_iter()
+            .filter(|tile| !self.impassable.contains(tile))
+            .collect()
+    }
+}
+
+impl RonAsset for MapAsset {
+    const EXTENSIONS: &'static [&'static str] = &["map.ron"];
+
+    fn validate(&self) -> Result<(), anyhow::Error> {
+        if self.cell_size <= 0.0 {
+            anyhow::bail!("cell_size must be positive, got {}", self.cell_size);
+        }
+
+        let tiles = self.tiles();
+        if let Some(tile) = tiles.iter().find(|(x, y, z)| x + y + z != 0) {
+            anyhow::bail!("{:?} is not a valid cube coordinate", tile);
+        }
+
+        let passable = self.passable_tiles();
+        for spawn in std::iter::once(&self.player_spawn).chain(self.mob_spawns.iter()) {
+            if !passable.contains(spawn) {
+                anyhow::bail!("spawn point {:?} is not on a passable tile", spawn);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// The map that is waiting to be spawned once it finishes loading.
+pub struct MapHandle(pub Handle<MapAsset>);
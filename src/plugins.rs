@@ -0,0 +1,250 @@
+use crate::ability::{AbilityDef, ChannelDef};
+use crate::events::*;
+use crate::loader::RonLoader;
+use crate::map::MapAsset;
+use crate::resources::{GamepadInput, InputBuffer, KeybindMenu, SimulationTime, Targeting};
+use crate::schedule::{GameSystem, SimulationStage, Step};
+use crate::systems::effects::AddEffectHandler;
+use crate::systems::*;
+use bevy::ecs::component::Component;
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+
+/// Plugin with everything that simulates the game: player actions,
+/// combat and mobs, plus the `MovementPlugin` and `AbilitiesPlugin`.
+/// The simulation runs in the fixed ticks of the `SimulationStage`, see
+/// `SimulationTime`. None of it needs a window, a GPU or an audio device,
+/// so it also runs under `MinimalPlugins` as long as there is an
+/// `AssetPlugin`. Insert `SimulationTime::stepped` before adding the
+/// plugin to run one tick per update.
+pub struct GameplayPlugin;
+
+impl Plugin for GameplayPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<SimulationTime>()
+            .init_resource::<Targeting>()
+            .init_resource::<InputBuffer>()
+            .add_stage_after(
+                CoreStage::Update,
+                SimulationStage,
+                SystemStage::single_threaded()
+                    .with_run_criteria(time::simulation_tick_criteria.system()),
+            )
+            .add_system_to_stage(CoreStage::First, time::simulation_time_system.system())
+            .add_system_set_to_stage(
+                SimulationStage,
+                // every one of these writes `CharState`, so their order is
+                // fixed: new actions first, then the buffered action once
+                // a cast is done and only then the next queued command
+                Step::new(GameSystem::Intent)
+                    .with_system(interpolation::restore_position_system.system())
+                    .with_system(player_action::player_action_system.system())
+                    .with_system(player_action::input_buffer_system.system())
+                    .with_system(player_action::command_queue_system.system())
+                    .with_system(mob_ai::mob_brain_system.system())
+                    .into(),
+            )
+            .add_system_set_to_stage(
+                SimulationStage,
+                Step::new(GameSystem::Combat)
+                    .after(GameSystem::Effects)
+                    .with_system(combat::health_system.system())
+                    .with_system(combat::death_system.system())
+                    .with_system(combat::respawn_system.system())
+                    .into(),
+            )
+            .add_system_to_stage(
+                SimulationStage,
+                interpolation::record_position_system
+                    .system()
+                    .after(GameSystem::Occupancy),
+            )
+            // only read by the hud so it is kept for frames, not ticks
+            .add_event::<NotEnoughEnergyEvent>();
+
+        add_simulation_event::<PlayerAction>(app);
+        add_simulation_event::<DamageEvent>(app);
+        add_simulation_event::<HealEvent>(app);
+        add_simulation_event::<DeathEvent>(app);
+        add_simulation_event::<AbilityEffectEvent>(app);
+
+        app.add_plugin(MovementPlugin).add_plugin(AbilitiesPlugin);
+    }
+}
+
+// Events that the simulation reads are kept until the end of the tick
+// after the one they were sent in, instead of for two frames. That way
+// none are missed on frames without a tick.
+fn add_simulation_event<T: Component>(app: &mut AppBuilder) {
+    app.init_resource::<Events<T>>().add_system_to_stage(
+        SimulationStage,
+        Events::<T>::update_system
+            .system()
+            .after(GameSystem::Occupancy),
+    );
+}
+
+/// Plugin that moves units along their paths on the `TileGraph`, or
+/// along the gamepad's left stick for the player, and keeps track of
+/// which tiles they occupy. Needs the `GameplayPlugin`.
+pub struct MovementPlugin;
+
+impl Plugin for MovementPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<GamepadInput>()
+            .add_system_set_to_stage(
+                SimulationStage,
+                Step::new(GameSystem::Movement)
+                    .after(GameSystem::Combat)
+                    .with_system(movement::movement_system.system())
+                    .with_system(movement::direct_movement_system.system())
+                    .into(),
+            )
+            .add_system_to_stage(
+                SimulationStage,
+                movement::occupancy_system
+                    .system()
+                    .label(GameSystem::Occupancy)
+                    .after(GameSystem::Movement),
+            );
+    }
+}
+
+/// Plugin with the ability definitions, the cast and channel systems,
+/// the built in ability effects and charge and energy regeneration.
+/// Needs the `GameplayPlugin`.
+pub struct AbilitiesPlugin;
+
+impl Plugin for AbilitiesPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_asset::<AbilityDef>()
+            .init_asset_loader::<RonLoader<AbilityDef>>()
+            .add_asset::<ChannelDef>()
+            .init_asset_loader::<RonLoader<ChannelDef>>()
+            // definitions are applied as soon as they load, not on a tick
+            .add_system(abilities::ability_def_system.system())
+            .add_system_set_to_stage(
+                SimulationStage,
+                Step::new(GameSystem::Abilities)
+                    .after(GameSystem::Intent)
+                    .with_system(abilities::cast_system.system())
+                    .with_system(abilities::lazer.system())
+                    .with_system(abilities::projectile_system.system())
+                    .with_system(abilities::charges_cooldown_system.system())
+                    .with_system(abilities::energy_regen_system.system())
+                    .with_system(effects::speed_buff_system.system())
+                    .into(),
+            )
+            .add_effect_handler(effects::TELEPORT, effects::teleport_effect.system())
+            .add_effect_handler(effects::PROJECTILE, effects::projectile_effect.system())
+            .add_effect_handler(effects::DAMAGE_AREA, effects::damage_area_effect.system())
+            .add_effect_handler(effects::BUFF, effects::buff_effect.system());
+    }
+}
+
+/// Plugin that turns mouse, keyboard and gamepad input into
+/// `PlayerAction` events using the user's controls. Needs a window
+/// and the `GameplayPlugin` for the events.
+pub struct InputPlugin;
+
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<KeybindMenu>()
+            .init_resource::<GamepadInput>()
+            .add_startup_system(setup::controls_setup_system.system())
+            .add_system_set(
+                SystemSet::new()
+                    .label(GameSystem::Input)
+                    .with_system(input::gamepad_system.system())
+                    .with_system(input::input_system.system()),
+            );
+    }
+}
+
+/// Plugin that writes the player's settings, then every `PlayerAction`
+/// and gamepad stick movement to a recording as the simulation acts on
+/// them, see `ReplaySettings`. Needs a `Recorder` resource and the
+/// `GameplayPlugin`.
+pub struct RecordPlugin;
+
+impl Plugin for RecordPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_to_stage(
+            SimulationStage,
+            // after the intent step so that replayed actions are recorded too
+            replay::record_system
+                .system()
+                .after(GameSystem::Intent)
+                .before(GameSystem::Abilities),
+        );
+    }
+}
+
+/// Plugin that plays back a recording by sending its `PlayerAction`s and
+/// moving the gamepad stick in the ticks they were recorded in, with
+/// controls that have the recorded settings. Use it instead of the
+/// `InputPlugin`. Needs a `Replay` resource and the `GameplayPlugin`.
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<GamepadInput>()
+            .add_startup_system(replay::replay_controls_setup_system.system())
+            .add_system_to_stage(
+                SimulationStage,
+                replay::replay_system.system().before(GameSystem::Intent),
+            );
+    }
+}
+
+/// Plugin that loads the arena map and spawns the cells, the
+/// `TileGraph` and the units on it once the map file is loaded.
+pub struct MapPlugin;
+
+impl Plugin for MapPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_asset::<MapAsset>()
+            .init_asset_loader::<RonLoader<MapAsset>>()
+            .add_startup_system(setup::map_setup_system.system())
+            .add_system(setup::spawn_map_system.system());
+    }
+}
+
+/// Plugin with the cameras and everything drawn on top of the game:
+/// health and energy bars, ability charges and cooldowns, range
+/// indicators, waypoint markers and the keybinding menu. Units are
+/// drawn in between simulation ticks so they move smoothly.
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<KeybindMenu>()
+            .init_resource::<Targeting>()
+            .add_startup_system(setup::camera_setup_system.system())
+            .add_startup_system(setup::ui_setup_system.system())
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                interpolation::interpolation_system
+                    .system()
+                    .label(GameSystem::Interpolation)
+                    .before(TransformSystem::TransformPropagate),
+            )
+            .add_system_set_to_stage(
+                CoreStage::PostUpdate,
+                SystemSet::new()
+                    .label(GameSystem::Presentation)
+                    .after(GameSystem::Interpolation)
+                    .with_system(targeting::range_indicator_system.system())
+                    .with_system(targeting::waypoint_marker_system.system())
+                    .with_system(hud::health_hud_system.system())
+                    .with_system(hud::energy_hud_system.system())
+                    .with_system(hud::energy_feedback_system.system())
+                    .with_system(hud::charge_battery_system.system())
+                    .with_system(hud::cooldown_sweep_system.system())
+                    .with_system(menu::toggle_menu_system.system())
+                    .with_system(menu::select_binding_system.system())
+                    .with_system(menu::rebind_system.system())
+                    .with_system(menu::menu_text_system.system()),
+            );
+    }
+}
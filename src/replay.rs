@@ -0,0 +1,247 @@
+use crate::components::{CastAbility, CastMode, UserControls, DEFAULT_INPUT_BUFFER};
+use crate::events::PlayerAction;
+use crate::resources::SimulationTime;
+use bevy::math::Vec2;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::Path;
+
+// The player's settings that change how their input plays out. They
+// are the first line of every recording, for example
+//
+// ```ron
+// (input_buffer: 0.2, cast_modes: {Dash: OnRelease})
+// (tick: 12, input: Action((action: Move, phase: Started, mouse_coords: (-150.0, 100.0, 1.0), queued: false)))
+// (tick: 40, input: Stick((0.0, 1.0)))
+// (tick: 52, input: Stick((0.0, 0.0)))
+// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ReplaySettings {
+    pub input_buffer: f32,
+    #[serde(default)]
+    pub cast_modes: FxHashMap<CastAbility, CastMode>,
+}
+
+impl Default for ReplaySettings {
+    fn default() -> Self {
+        Self {
+            input_buffer: DEFAULT_INPUT_BUFFER,
+            cast_modes: FxHashMap::default(),
+        }
+    }
+}
+
+impl From<&UserControls> for ReplaySettings {
+    fn from(controls: &UserControls) -> Self {
+        Self {
+            input_buffer: controls.input_buffer,
+            cast_modes: controls.cast_modes.clone(),
+        }
+    }
+}
+
+impl ReplaySettings {
+    // The default controls with these settings.
+    pub fn controls(&self) -> UserControls {
+        UserControls {
+            input_buffer: self.input_buffer,
+            cast_modes: self.cast_modes.clone(),
+            ..UserControls::new()
+        }
+    }
+}
+
+// Input of the player in a recording and the tick the simulation acted
+// on it in, one per line after the `ReplaySettings`.
+//
+// Ticks are counted from the first tick the player was on the map, so
+// that a replay doesn't depend on how long the map took to load.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct RecordedInput {
+    pub tick: u64,
+    pub input: ReplayInput,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum ReplayInput {
+    Action(PlayerAction),
+    // The gamepad's left stick moved, see `GamepadInput::movement`.
+    Stick(Vec2),
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    Serialize(ron::Error),
+    Parse { line: usize, err: ron::Error },
+    // The recording doesn't even have its settings.
+    Empty,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "could not access the recording: {}", err),
+            Self::Serialize(err) => write!(f, "could not write the input: {}", err),
+            Self::Parse { line, err } => {
+                write!(f, "could not parse line {} of the recording: {}", line, err)
+            }
+            Self::Empty => write!(f, "the recording is empty"),
+        }
+    }
+}
+
+// Counts the ticks since the player was first on the map.
+#[derive(Default)]
+struct ReplayClock {
+    first_tick: Option<u64>,
+}
+
+impl ReplayClock {
+    fn tick(&mut self, time: &SimulationTime, player_spawned: bool) -> Option<u64> {
+        if player_spawned && self.first_tick.is_none() {
+            self.first_tick = Some(time.tick());
+        }
+        self.first_tick.map(|first_tick| time.tick() - first_tick)
+    }
+}
+
+// Writes the player's input to a recording as it is acted on. Every
+// line is written out right away so the recording survives a crash.
+pub struct Recorder {
+    // Dropped after the first error so it isn't reported on every tick.
+    writer: Option<LineWriter<File>>,
+    clock: ReplayClock,
+    settings_written: bool,
+    stick: Vec2,
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        let file = File::create(path).map_err(ReplayError::Io)?;
+        Ok(Self {
+            writer: Some(LineWriter::new(file)),
+            clock: ReplayClock::default(),
+            settings_written: false,
+            stick: Vec2::ZERO,
+        })
+    }
+
+    // Tick of the recording that the simulation is in, none until the
+    // player is on the map. Has to be called every tick.
+    pub fn tick(&mut self, time: &SimulationTime, player_spawned: bool) -> Option<u64> {
+        self.clock.tick(time, player_spawned)
+    }
+
+    // Records the player's input in one tick, the settings are written
+    // before the first one. The stick is only written when it moved.
+    pub fn record(
+        &mut self,
+        tick: u64,
+        settings: impl FnOnce() -> ReplaySettings,
+        actions: &[PlayerAction],
+        stick: Vec2,
+    ) -> Result<(), ReplayError> {
+        if !self.settings_written {
+            self.settings_written = true;
+            self.write(&settings())?;
+        }
+        for action in actions {
+            self.write(&RecordedInput {
+                tick,
+                input: ReplayInput::Action(*action),
+            })?;
+        }
+        if stick != self.stick {
+            self.stick = stick;
+            self.write(&RecordedInput {
+                tick,
+                input: ReplayInput::Stick(stick),
+            })?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, line: &impl Serialize) -> Result<(), ReplayError> {
+        let writer = match self.writer.as_mut() {
+            Some(writer) => writer,
+            None => return Ok(()),
+        };
+        let line = ron::ser::to_string(line).map_err(ReplayError::Serialize)?;
+        if let Err(err) = writeln!(writer, "{}", line) {
+            self.writer = None;
+            return Err(ReplayError::Io(err));
+        }
+        Ok(())
+    }
+}
+
+// Recorded input that is still to be replayed, in tick order.
+pub struct Replay {
+    settings: ReplaySettings,
+    inputs: VecDeque<RecordedInput>,
+    clock: ReplayClock,
+}
+
+impl Replay {
+    pub fn new(settings: ReplaySettings, mut inputs: Vec<RecordedInput>) -> Self {
+        inputs.sort_by_key(|recorded| recorded.tick);
+        Self {
+            settings,
+            inputs: inputs.into(),
+            clock: ReplayClock::default(),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        let contents = std::fs::read_to_string(path).map_err(ReplayError::Io)?;
+        let mut lines = contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+        let parse_error = |index: usize| {
+            move |err: ron::Error| ReplayError::Parse {
+                line: index + 1,
+                err,
+            }
+        };
+
+        let settings = match lines.next() {
+            Some((index, line)) => ron::de::from_str(line).map_err(parse_error(index))?,
+            None => return Err(ReplayError::Empty),
+        };
+        let inputs = lines
+            .map(|(index, line)| ron::de::from_str(line).map_err(parse_error(index)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self::new(settings, inputs))
+    }
+
+    pub fn settings(&self) -> &ReplaySettings {
+        &self.settings
+    }
+
+    // Takes the input due in the current tick. Has to be called every tick.
+    pub fn next_inputs(&mut self, time: &SimulationTime, player_spawned: bool) -> Vec<ReplayInput> {
+        let tick = match self.clock.tick(time, player_spawned) {
+            Some(tick) => tick,
+            None => return Vec::new(),
+        };
+        let due = self
+            .inputs
+            .iter()
+            .take_while(|recorded| recorded.tick <= tick)
+            .count();
+        self.inputs
+            .drain(..due)
+            .map(|recorded| recorded.input)
+            .collect()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
@@ -1,2 +1,161 @@
-use crate::components::*;
+use crate::components::CastAbility;
+use crate::events::{Action, PlayerAction};
 use bevy::prelude::*;
+use rustc_hash::FxHashSet;
+use std::time::Duration;
+
+// State of the keybinding menu.
+#[derive(Default)]
+pub struct KeybindMenu {
+    pub open: bool,
+
+    // The action waiting for a new binding after its row was clicked.
+    pub rebinding: Option<Action>,
+
+    // Mouse buttons are only captured once the click that selected the
+    // row has been released, otherwise that click would become the binding.
+    pub armed: bool,
+
+    // Feedback shown at the bottom of the menu.
+    pub message: String,
+}
+
+// The gamepad the player is using and where its sticks point.
+pub struct GamepadInput {
+    pub active: Option<Gamepad>,
+
+    // Direction of the left stick, zero while it is at rest.
+    pub movement: Vec2,
+
+    // Last direction the player aimed in with the right stick, or moved
+    // in with the left stick when they aren't aiming. Always normalized.
+    pub aim: Vec2,
+}
+
+impl Default for GamepadInput {
+    fn default() -> Self {
+        Self {
+            active: None,
+            movement: Vec2::ZERO,
+            aim: Vec2::X,
+        }
+    }
+}
+
+// The cast ability the player is picking a target for, if any.
+// Only abilities that don't use `CastMode::Quick` are aimed.
+#[derive(Default)]
+pub struct Targeting {
+    pub ability: Option<CastAbility>,
+}
+
+// The last move or cast the player pressed while casting and when it
+// was pressed, in seconds since startup.
+#[derive(Default)]
+pub struct InputBuffer {
+    pub action: Option<(PlayerAction, f64)>,
+}
+
+// Names of the ability effects that have a handler, see
+// `effects::AddEffectHandler`.
+#[derive(Default)]
+pub struct EffectRegistry {
+    names: FxHashSet<String>,
+    handlers: usize,
+}
+
+impl EffectRegistry {
+    // Registers a handler for `name` and returns how many handlers
+    // were registered before it.
+    pub fn register(&mut self, name: &str) -> usize {
+        self.names.insert(name.to_string());
+        self.handlers += 1;
+        self.handlers - 1
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.contains(name)
+    }
+}
+
+// Clock of the simulation, which runs in fixed ticks of `step` seconds
+// so that the same input always plays out the same way. Normally the
+// frame times add up to decide how many ticks are due each frame. When
+// `frame` is set every frame is taken to last that long instead, however
+// long it really took.
+pub struct SimulationTime {
+    pub step: Duration,
+    pub frame: Option<Duration>,
+
+    // Frame time that hasn't been simulated yet.
+    accumulator: Duration,
+    tick: u64,
+}
+
+impl Default for SimulationTime {
+    fn default() -> Self {
+        Self {
+            step: Duration::from_secs_f64(1.0 / 60.0),
+            frame: None,
+            accumulator: Duration::default(),
+            tick: 0,
+        }
+    }
+}
+
+impl SimulationTime {
+    // Longest frame that is caught up on, anything longer slows the
+    // game down instead of running a burst of ticks.
+    const MAX_FRAME: Duration = Duration::from_millis(250);
+
+    // Runs exactly one tick every frame.
+    pub fn stepped(step: Duration) -> Self {
+        Self::with_frame(step, step)
+    }
+
+    pub fn with_frame(step: Duration, frame: Duration) -> Self {
+        Self {
+            step,
+            frame: Some(frame),
+            ..Default::default()
+        }
+    }
+
+    // Adds a frame's worth of time to simulate.
+    pub fn add_frame(&mut self, frame: Duration) {
+        self.accumulator += self.frame.unwrap_or_else(|| frame.min(Self::MAX_FRAME));
+    }
+
+    // Starts the next tick if one is due.
+    pub fn next_tick(&mut self) -> bool {
+        if self.accumulator >= self.step {
+            self.accumulator -= self.step;
+            self.tick += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Number of the current tick, the first tick is 1.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    // How far the frame is into the next tick, from 0 to 1.
+    pub fn overstep(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+
+    pub fn delta(&self) -> Duration {
+        self.step
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.step.as_secs_f32()
+    }
+
+    pub fn seconds_since_startup(&self) -> f64 {
+        self.tick as f64 * self.step.as_secs_f64()
+    }
+}
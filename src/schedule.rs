@@ -0,0 +1,86 @@
+use bevy::prelude::*;
+
+// Stage that runs the simulation in fixed ticks of `SimulationTime::step`.
+// It runs after `CoreStage::Update` as many times per frame as there are
+// ticks due, which can be none at all on fast frames.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, StageLabel)]
+pub struct SimulationStage;
+
+// Steps that the game's systems run in:
+//
+// 1. `Input` turns mouse, keyboard and gamepad input into `PlayerAction`s
+//    every frame in `CoreStage::Update`.
+//
+// Every tick of the `SimulationStage`:
+//
+// 2. `Intent` turns player actions, queued commands and mob decisions
+//    into `CharState`s.
+// 3. `Abilities` runs casts and channels, moves projectiles and
+//    regenerates charges and energy.
+// 4. `Effects` applies the effects of finished casts.
+// 5. `Combat` applies damage and handles deaths and respawns.
+// 6. `Movement` moves units along their paths or the gamepad stick.
+// 7. `Occupancy` puts every unit in the tile it ended up in.
+//
+// Then every frame in `CoreStage::PostUpdate`:
+//
+// 8. `Interpolation` places units between their last two ticks.
+// 9. `Presentation` updates the hud, the indicators and the menu.
+//
+// So a command issued on one frame is acted on by the next tick.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub enum GameSystem {
+    Input,
+    Intent,
+    Abilities,
+    Effects,
+    Combat,
+    Movement,
+    Occupancy,
+    Interpolation,
+    Presentation,
+}
+
+// Label of the system at some position in a step.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+struct StepOrder(GameSystem, usize);
+
+// Builds the system set for one step of the simulation. The systems run
+// one after another in the order they are added, so that every tick
+// runs them, and applies their commands, in the same order.
+pub struct Step {
+    label: GameSystem,
+    set: SystemSet,
+    len: usize,
+}
+
+impl Step {
+    pub fn new(label: GameSystem) -> Self {
+        Self {
+            set: SystemSet::new().label(label.clone()),
+            label,
+            len: 0,
+        }
+    }
+
+    pub fn after(mut self, label: GameSystem) -> Self {
+        self.set = self.set.after(label);
+        self
+    }
+
+    pub fn with_system(mut self, system: impl ParallelSystemDescriptorCoercion) -> Self {
+        let mut system = system.label(StepOrder(self.label.clone(), self.len));
+        if self.len > 0 {
+            system = system.after(StepOrder(self.label.clone(), self.len - 1));
+        }
+        self.set = self.set.with_system(system);
+        self.len += 1;
+        self
+    }
+}
+
+impl From<Step> for SystemSet {
+    fn from(step: Step) -> Self {
+        step.set
+    }
+}
@@ -1,8 +1,14 @@
+use crate::ability::{AbilityDef, ChannelDef};
 use crate::components::*;
+use crate::entities::BeamBundle;
+use crate::events::{AbilityEffectEvent, DamageEvent, NotEnoughEnergyEvent};
+use crate::resources::{EffectRegistry, SimulationTime};
 use bevy::prelude::*;
+use rustc_hash::FxHashSet;
+use std::time::Duration;
 
 pub fn charges_cooldown_system(
-    time: Res<Time>,
+    time: Res<SimulationTime>,
     mut query: Query<(&mut CooldownTimer, &Cooldown, &mut Charges, &MaxCharges)>,
 ) {
     let delta = time.delta();
@@ -20,54 +26,361 @@ pub fn charges_cooldown_system(
     }
 }
 
-pub fn dash(
-    time: Res<Time>,
-    mut player_query: Query<(&mut CharState, &mut Transform), With<Player>>,
-    mut dash_query: Query<
-        (&mut Charges, &mut CooldownTimer, &mut CastTimer, &CastTime),
-        With<Dash>,
+/// Passive energy regeneration, living units regain energy
+/// up to their max energy over time.
+pub fn energy_regen_system(
+    time: Res<SimulationTime>,
+    mut query: Query<(&mut Energy, &MaxEnergy, &mut EnergyRegen, &CharState)>,
+) {
+    for (mut energy, max_energy, mut regen, state) in query.iter_mut() {
+        if *state == CharState::Dead {
+            continue;
+        }
+        regen.timer.tick(time.delta());
+        let gained = regen.amount * regen.timer.times_finished() as i64;
+        if gained > 0 && energy.0 < max_energy.0 {
+            energy.0 = (energy.0 + gained).min(max_energy.0);
+        }
+    }
+}
+
+/// System that fills in ability components from their definition once
+/// it has loaded, or right away for abilities spawned after it loaded,
+/// and again whenever the file is changed. New abilities start with all
+/// of their charges, changed ones keep their charges and cooldown.
+pub fn ability_def_system(
+    mut def_events: EventReader<AssetEvent<AbilityDef>>,
+    defs: Res<Assets<AbilityDef>>,
+    mut query: Query<(
+        &Handle<AbilityDef>,
+        ChangeTrackers<Handle<AbilityDef>>,
+        &mut CastRange,
+        &mut Cooldown,
+        &mut CooldownTimer,
+        &mut Charges,
+        &mut MaxCharges,
+        &mut CastTime,
+    )>,
+) {
+    let mut created = FxHashSet::default();
+    let mut modified = FxHashSet::default();
+    for event in def_events.iter() {
+        match event {
+            AssetEvent::Created { handle } => {
+                created.insert(handle.id);
+            }
+            AssetEvent::Modified { handle } => {
+                modified.insert(handle.id);
+            }
+            AssetEvent::Removed { .. } => (),
+        }
+    }
+
+    for (
+        handle,
+        handle_tracker,
+        mut cast_range,
+        mut cooldown,
+        mut cooldown_timer,
+        mut charges,
+        mut max_charges,
+        mut cast_time,
+    ) in query.iter_mut()
+    {
+        let new = handle_tracker.is_added() || created.contains(&handle.id);
+        if !new && !modified.contains(&handle.id) {
+            continue;
+        }
+        let def = match defs.get(handle) {
+            Some(def) => def,
+            None => continue,
+        };
+
+        cast_range.0 = def.range;
+        cooldown.0 = def.cooldown;
+        cast_time.0 = def.cast_time;
+        max_charges.0 = def.charges;
+
+        let duration = Duration::from_secs_f32(def.cooldown);
+        if new {
+            charges.0 = def.charges;
+            cooldown_timer.0 = Timer::new(duration, true);
+            cooldown_timer.0.pause();
+        } else {
+            charges.0 = charges.0.min(def.charges);
+            cooldown_timer.0.set_duration(duration);
+            if charges.0 == max_charges.0 {
+                cooldown_timer.0.reset();
+                cooldown_timer.0.pause();
+            } else if cooldown_timer.0.paused() {
+                cooldown_timer.0.unpause();
+            }
+        }
+    }
+}
+
+/// Casting state machine shared by every cast ability of every unit.
+/// Starting a cast uses up a charge of the caster's own ability and its
+/// energy cost, casts without enough energy are refused with a
+/// `NotEnoughEnergyEvent`. Once the cast time is up an
+/// `AbilityEffectEvent` is sent for each of the ability's effects.
+/// Casts that are interrupted, or whose caster dies, start over the
+/// next time without giving back what they used up.
+#[allow(clippy::too_many_arguments)]
+pub fn cast_system(
+    time: Res<SimulationTime>,
+    audio: Option<Res<Audio>>,
+    asset_server: Res<AssetServer>,
+    defs: Res<Assets<AbilityDef>>,
+    registry: Res<EffectRegistry>,
+    mut effect_events: EventWriter<AbilityEffectEvent>,
+    mut not_enough_energy: EventWriter<NotEnoughEnergyEvent>,
+    mut caster_query: Query<(Entity, &mut CharState, &Transform, Option<&mut Energy>)>,
+    mut ability_query: Query<(
+        &AbilityOwner,
+        &CastAbility,
+        &Handle<AbilityDef>,
+        &mut Charges,
+        &mut CooldownTimer,
+        &mut CastTimer,
+        &CastTime,
+    )>,
+) {
+    // the caster left the cast before it went off
+    for (owner, class, _, _, _, mut cast_timer, _) in ability_query.iter_mut() {
+        let casting = matches!(
+            caster_query.get(owner.0),
+            Ok((_, CharState::Casting((ability, _)), ..)) if ability == class
+        );
+        if !casting && !cast_timer.0.paused() {
+            cast_timer.0.reset();
+            cast_timer.0.pause();
+        }
+    }
+
+    for (caster, mut state, transform, mut energy) in caster_query.iter_mut() {
+        let (ability, target) = match *state {
+            CharState::Casting(cast) => cast,
+            _ => continue,
+        };
+        let found = ability_query
+            .iter_mut()
+            .find(|(owner, class, ..)| owner.0 == caster && **class == ability);
+        let (_, _, handle, mut charges, mut cooldown_timer, mut cast_timer, cast_time) = match found
+        {
+            Some(found) => found,
+            // the caster doesn't have this ability
+            None => {
+                *state = CharState::Idle;
+                continue;
+            }
+        };
+
+        let def = match defs.get(handle) {
+            Some(def) => def,
+            // not loaded yet so there is nothing to cast
+            None => {
+                *state = CharState::Idle;
+                continue;
+            }
+        };
+
+        let after_cast = if def.move_after_cast {
+            CharState::Moving(target, None)
+        } else {
+            CharState::Idle
+        };
+
+        // if the cast timer is paused then we need to start casting
+        if cast_timer.0.paused() {
+            // out of charges so the cast fizzles
+            if charges.0 == 0 {
+                *state = after_cast;
+                continue;
+            }
+            if energy.as_ref().map_or(0, |energy| energy.0) < def.energy_cost {
+                *state = CharState::Idle;
+                not_enough_energy.send(NotEnoughEnergyEvent { unit: caster });
+                continue;
+            }
+            if let Some(energy) = energy.as_mut() {
+                energy.0 -= def.energy_cost;
+            }
+            charges.0 -= 1;
+            // the cast time might have changed since the last cast
+            cast_timer.0 = Timer::from_seconds(cast_time.0, false);
+            // cooldown timer might have been paused if we are at
+            // max charges so start that since we consumed one.
+            if cooldown_timer.0.paused() {
+                cooldown_timer.0.unpause();
+            }
+        } else if cast_timer.0.finished() {
+            // reset the cast timer for next time when done casting
+            cast_timer.0.reset();
+            cast_timer.0.pause();
+
+            // effects can't reach further than the ability's range
+            let origin = transform.translation;
+            let offset = target.0 - origin;
+            let effect_target = if offset.length() > def.range {
+                origin + def.range * offset.normalize()
+            } else {
+                target.0
+            };
+
+            for effect in def.effects.iter() {
+                if registry.contains(&effect.name) {
+                    effect_events.send(AbilityEffectEvent {
+                        caster,
+                        effect: effect.clone(),
+                        origin,
+                        target: effect_target,
+                    });
+                } else {
+                    warn!("no handler for the ability effect \"{}\"", effect.name);
+                }
+            }
+            if let (Some(sound), Some(audio)) = (&def.sound, &audio) {
+                audio.play(asset_server.load(sound.as_str()));
+            }
+            *state = after_cast;
+        // if the cast timer is running and not finished then continue
+        } else {
+            cast_timer.0.tick(time.delta());
+        }
+    }
+}
+
+/// Moves projectiles along their velocity and despawns them once
+/// they have flown their max range or hit a unit other than the one
+/// that fired them.
+pub fn projectile_system(
+    mut commands: Commands,
+    time: Res<SimulationTime>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut query: Query<
+        (
+            Entity,
+            &AbilityOwner,
+            &Velocity,
+            &MaxRange,
+            &Damage,
+            &mut DistanceTraveled,
+            &mut Transform,
+        ),
+        With<Projectile>,
     >,
+    unit_query: Query<(Entity, &Transform, &CharState), (With<Health>, Without<Projectile>)>,
 ) {
-    let dash_range = 400.0;
-    if let Ok((mut state, mut transform)) = player_query.single_mut() {
-        if let CharState::Casting(ability) = *state {
-            if ability.0 == CastAbility::Dash {
-                if let Ok((mut dash_charges, mut cooldown_timer, mut cast_timer, cast_time)) =
-                    dash_query.single_mut()
-                {
-                    // if we don't have any charges then we cannot cast, so just
-                    // keep moving towards the dash location
-                    if dash_charges.0 == 0 {
-                        *state = CharState::Moving(ability.1, None);
-                    // if the cast timer is paused then we need to start casting
-                    } else if cast_timer.0.paused() && dash_charges.0 > 0 {
-                        dash_charges.0 -= 1;
-                        cast_timer.0.unpause();
-                        // cooldown timer might have been paused if we are at
-                        // max charges so start that since we consumed one.
-                        if cooldown_timer.0.paused() {
-                            cooldown_timer.0.unpause();
-                        }
-                    } else if cast_timer.0.finished() {
-                        // reset the cast timer for next time when done casting
-                        cast_timer.0 = Timer::from_seconds(cast_time.0, false);
-                        cast_timer.0.pause();
-                        // perform the dash
-                        let mouse_coords = ability.1 .0;
-                        let direction = mouse_coords - transform.translation;
-                        if direction.length() < dash_range {
-                            transform.translation += direction;
-                        } else {
-                            transform.translation += dash_range * direction.normalize();
-                        }
-                        // keep moving toward dash destination
-                        *state = CharState::Moving(ability.1, None);
-                    // if the cast timer is running and not finished then continue
-                    } else {
-                        cast_timer.0.tick(time.delta());
+    let hit_radius = 40.0;
+    let delta_seconds = time.delta_seconds();
+    for (entity, owner, velocity, max_range, damage, mut traveled, mut transform) in
+        query.iter_mut()
+    {
+        let step = velocity.0 * delta_seconds;
+        transform.translation += step;
+        traveled.0 += step.length();
+
+        let position = transform.translation.truncate();
+        let hit = unit_query.iter().find(|(unit, unit_transform, state)| {
+            *unit != owner.0
+                && **state != CharState::Dead
+                && unit_transform.translation.truncate().distance(position) < hit_radius
+        });
+
+        if let Some((unit, _, _)) = hit {
+            damage_events.send(DamageEvent {
+                target: unit,
+                amount: damage.0,
+            });
+            commands.entity(entity).despawn();
+        } else if traveled.0 >= max_range.0 {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Channel system for the lazer. While a unit is channeling its lazer
+/// the beam is drawn from the unit to the targeted coords, the energy
+/// cost of its `ChannelDef` is drained every `drain_interval` and the
+/// lazer sound is looped. The
+/// channel ends when the player action system sees the input being
+/// released or when there isn't enough energy left for another tick.
+#[allow(clippy::too_many_arguments)]
+pub fn lazer(
+    mut commands: Commands,
+    time: Res<SimulationTime>,
+    audio: Option<Res<Audio>>,
+    asset_server: Res<AssetServer>,
+    defs: Res<Assets<ChannelDef>>,
+    mut not_enough_energy: EventWriter<NotEnoughEnergyEvent>,
+    mut unit_query: Query<(Entity, &mut CharState, &mut Energy, &Transform), Without<LazerBeam>>,
+    mut lazer_query: Query<
+        (
+            &AbilityOwner,
+            &Handle<ChannelDef>,
+            &mut ChannelTimer,
+            &mut SoundTimer,
+        ),
+        With<Lazer>,
+    >,
+    mut beam_query: Query<(Entity, &AbilityOwner, &mut Transform), With<LazerBeam>>,
+) {
+    for (owner, handle, mut channel_timer, mut sound_timer) in lazer_query.iter_mut() {
+        let (unit, mut state, mut energy, transform) = match unit_query.get_mut(owner.0) {
+            Ok(unit) => unit,
+            Err(_) => continue,
+        };
+        if let CharState::Channeling((ChannelAbility::Lazer, target)) = *state {
+            let def = match defs.get(handle) {
+                Some(def) => def,
+                // not loaded yet so there is nothing to channel
+                None => {
+                    *state = CharState::Idle;
+                    continue;
+                }
+            };
+            let beam = beam_query
+                .iter_mut()
+                .find(|(_, beam_owner, _)| beam_owner.0 == unit);
+            if energy.0 < def.energy_cost {
+                *state = CharState::Idle;
+                not_enough_energy.send(NotEnoughEnergyEvent { unit });
+            } else if let Some((_, _, mut beam_transform)) = beam {
+                // beam already exists so keep it between the unit and the target
+                *beam_transform = BeamBundle::transform(transform.translation, target.0);
+
+                if sound_timer.0.tick(time.delta()).just_finished() {
+                    if let Some(audio) = &audio {
+                        audio.play(asset_server.load("lazer.wav"));
                     }
                 }
+
+                channel_timer.0.tick(time.delta());
+                let drained = def.energy_cost * channel_timer.0.times_finished() as i64;
+                if drained > 0 {
+                    energy.0 = (energy.0 - drained).max(0);
+                }
+            } else {
+                // channel just started
+                commands.spawn_bundle(BeamBundle::new(unit, transform.translation, target.0));
+                if let Some(audio) = &audio {
+                    audio.play(asset_server.load("lazer.wav"));
+                }
+                channel_timer.0 = Timer::from_seconds(def.drain_interval, true);
+                sound_timer.0.reset();
             }
         }
     }
+
+    // clean up beams once their unit's channel has ended for any reason
+    for (beam, owner, _) in beam_query.iter_mut() {
+        let channeling = matches!(
+            unit_query.get(owner.0),
+            Ok((_, CharState::Channeling((ChannelAbility::Lazer, _)), ..))
+        );
+        if !channeling {
+            commands.entity(beam).despawn();
+        }
+    }
 }
@@ -0,0 +1,121 @@
+use crate::components::*;
+use crate::events::*;
+use crate::resources::SimulationTime;
+use crate::systems::movement::TileGraph;
+use bevy::prelude::*;
+
+/// System that applies damage and heal events to unit health.
+/// Health is kept between zero and max health and a death event
+/// is sent the moment a unit's health reaches zero. Dead units
+/// ignore any further damage or healing.
+pub fn health_system(
+    mut damage_events: EventReader<DamageEvent>,
+    mut heal_events: EventReader<HealEvent>,
+    mut death_events: EventWriter<DeathEvent>,
+    mut query: Query<(&mut Health, &MaxHealth, &CharState)>,
+) {
+    for damage in damage_events.iter() {
+        if let Ok((mut health, _, state)) = query.get_mut(damage.target) {
+            if *state == CharState::Dead || health.0 <= 0 {
+                continue;
+            }
+            health.0 = (health.0 - damage.amount).max(0);
+            if health.0 == 0 {
+                death_events.send(DeathEvent {
+                    entity: damage.target,
+                });
+            }
+        }
+    }
+
+    for heal in heal_events.iter() {
+        if let Ok((mut health, max_health, state)) = query.get_mut(heal.target) {
+            if *state == CharState::Dead || health.0 <= 0 {
+                continue;
+            }
+            health.0 = (health.0 + heal.amount).min(max_health.0);
+        }
+    }
+}
+
+/// System that handles units dying. Mobs are removed from the world
+/// along with their abilities and their tile is freed up for pathing.
+/// Projectiles they fired keep flying. The player is put in the dead
+/// state and hidden until the respawn timer runs out.
+pub fn death_system(
+    mut commands: Commands,
+    mut death_events: EventReader<DeathEvent>,
+    mut q_graph: Query<&mut TileGraph>,
+    mob_query: Query<Entity, With<Mob>>,
+    ability_query: Query<(Entity, &AbilityOwner), Or<(With<CastAbility>, With<Lazer>)>>,
+    mut player_query: Query<(&mut CharState, &mut Visible), With<Player>>,
+) {
+    for death in death_events.iter() {
+        if let Ok(mut graph) = q_graph.single_mut() {
+            graph.vacate(death.entity);
+        }
+
+        if mob_query.get(death.entity).is_ok() {
+            commands.entity(death.entity).despawn();
+            for (ability, owner) in ability_query.iter() {
+                if owner.0 == death.entity {
+                    commands.entity(ability).despawn();
+                }
+            }
+        } else if let Ok((mut state, mut visible)) = player_query.get_mut(death.entity) {
+            *state = CharState::Dead;
+            visible.is_visible = false;
+            commands
+                .entity(death.entity)
+                .insert(RespawnTimer(Timer::from_seconds(3.0, false)));
+        }
+    }
+}
+
+/// System that brings dead players back at their spawn point
+/// with full health and energy once their respawn timer is done.
+pub fn respawn_system(
+    mut commands: Commands,
+    time: Res<SimulationTime>,
+    mut query: Query<
+        (
+            Entity,
+            &mut RespawnTimer,
+            &mut CharState,
+            &mut Health,
+            &MaxHealth,
+            &mut Energy,
+            &MaxEnergy,
+            &SpawnPoint,
+            &mut Transform,
+            &mut SimPosition,
+            &mut Visible,
+        ),
+        With<Player>,
+    >,
+) {
+    for (
+        entity,
+        mut timer,
+        mut state,
+        mut health,
+        max_health,
+        mut energy,
+        max_energy,
+        spawn_point,
+        mut transform,
+        mut sim_position,
+        mut visible,
+    ) in query.iter_mut()
+    {
+        if timer.0.tick(time.delta()).finished() {
+            health.0 = max_health.0;
+            energy.0 = max_energy.0;
+            transform.translation = spawn_point.0;
+            sim_position.teleport(spawn_point.0);
+            visible.is_visible = true;
+            *state = CharState::Idle;
+            commands.entity(entity).remove::<RespawnTimer>();
+        }
+    }
+}
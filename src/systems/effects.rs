@@ -0,0 +1,168 @@
+use crate::components::*;
+use crate::entities::ProjectileBundle;
+use crate::events::{AbilityEffectEvent, DamageEvent};
+use crate::resources::{EffectRegistry, SimulationTime};
+use crate::schedule::{GameSystem, SimulationStage};
+use crate::systems::movement::TileGraph;
+use bevy::prelude::*;
+
+// Names that abilities use for the built in effects.
+pub const TELEPORT: &str = "teleport";
+pub const PROJECTILE: &str = "projectile";
+pub const DAMAGE_AREA: &str = "damage_area";
+pub const BUFF: &str = "buff";
+
+// Label of the effect handler that was added at some position.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+struct EffectHandler(usize);
+
+pub trait AddEffectHandler {
+    // Adds a system to the `Effects` step that handles the ability
+    // effects named `name` and registers the name so abilities can use
+    // it. Handlers run one after another in the order they are added.
+    fn add_effect_handler(
+        &mut self,
+        name: &str,
+        system: impl ParallelSystemDescriptorCoercion,
+    ) -> &mut Self;
+}
+
+impl AddEffectHandler for AppBuilder {
+    fn add_effect_handler(
+        &mut self,
+        name: &str,
+        system: impl ParallelSystemDescriptorCoercion,
+    ) -> &mut Self {
+        let index = self
+            .world_mut()
+            .get_resource_or_insert_with(EffectRegistry::default)
+            .register(name);
+        let mut system = system
+            .label(EffectHandler(index))
+            .label(GameSystem::Effects)
+            .after(GameSystem::Abilities);
+        if index > 0 {
+            system = system.after(EffectHandler(index - 1));
+        }
+        self.add_system_to_stage(SimulationStage, system)
+    }
+}
+
+/// Moves the caster to the target, or as close to it as the map allows.
+pub fn teleport_effect(
+    mut effect_events: EventReader<AbilityEffectEvent>,
+    q_graph: Query<&TileGraph>,
+    mut query: Query<(&mut Transform, &mut CharState, Option<&mut SimPosition>)>,
+) {
+    for event in effect_events
+        .iter()
+        .filter(|event| event.effect.name == TELEPORT)
+    {
+        if let Ok((mut transform, mut state, sim_position)) = query.get_mut(event.caster) {
+            let destination = Vec3::new(event.target.x, event.target.y, transform.translation.z);
+            // never land inside a wall or off the map
+            transform.translation = match q_graph.single() {
+                Ok(graph) => graph.landing_point(transform.translation, destination),
+                Err(_) => destination,
+            };
+            if let Some(mut sim_position) = sim_position {
+                sim_position.teleport(transform.translation);
+            }
+            // a path from before the teleport is no good anymore
+            if let CharState::Moving(destination, Some(_)) = *state {
+                *state = CharState::Moving(destination, None);
+            }
+        }
+    }
+}
+
+/// Fires a projectile from the caster towards the target.
+/// Params: `speed`, `range` and `damage`.
+pub fn projectile_effect(
+    mut commands: Commands,
+    mut effect_events: EventReader<AbilityEffectEvent>,
+) {
+    for event in effect_events
+        .iter()
+        .filter(|event| event.effect.name == PROJECTILE)
+    {
+        commands.spawn_bundle(ProjectileBundle::new(
+            event.caster,
+            event.origin,
+            event.target,
+            event.effect.param("speed", 800.0),
+            event.effect.param("range", 600.0),
+            event.effect.param("damage", 25.0) as i64,
+        ));
+    }
+}
+
+/// Damages every living unit other than the caster around the target.
+/// Params: `radius` and `damage`.
+pub fn damage_area_effect(
+    mut effect_events: EventReader<AbilityEffectEvent>,
+    mut damage_events: EventWriter<DamageEvent>,
+    query: Query<(Entity, &Transform, &CharState), With<Health>>,
+) {
+    for event in effect_events
+        .iter()
+        .filter(|event| event.effect.name == DAMAGE_AREA)
+    {
+        let radius = event.effect.param("radius", 100.0);
+        let amount = event.effect.param("damage", 10.0) as i64;
+        let center = event.target.truncate();
+        for (unit, transform, state) in query.iter() {
+            if unit != event.caster
+                && *state != CharState::Dead
+                && transform.translation.truncate().distance(center) <= radius
+            {
+                damage_events.send(DamageEvent {
+                    target: unit,
+                    amount,
+                });
+            }
+        }
+    }
+}
+
+/// Speeds up the caster for a while, casting it again refreshes the
+/// duration. Params: `speed` multiplier and `duration` in seconds.
+pub fn buff_effect(
+    mut commands: Commands,
+    mut effect_events: EventReader<AbilityEffectEvent>,
+    mut query: Query<(&mut MovementSpeed, Option<&mut SpeedBuff>)>,
+) {
+    for event in effect_events
+        .iter()
+        .filter(|event| event.effect.name == BUFF)
+    {
+        let duration = event.effect.param("duration", 3.0);
+        if let Ok((mut speed, buff)) = query.get_mut(event.caster) {
+            match buff {
+                Some(mut buff) => buff.timer = Timer::from_seconds(duration, false),
+                None => {
+                    let base_speed = speed.0;
+                    speed.0 *= event.effect.param("speed", 1.5);
+                    commands.entity(event.caster).insert(SpeedBuff {
+                        base_speed,
+                        timer: Timer::from_seconds(duration, false),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Puts back the movement speed of units whose speed buff ran out.
+pub fn speed_buff_system(
+    mut commands: Commands,
+    time: Res<SimulationTime>,
+    mut query: Query<(Entity, &mut SpeedBuff, &mut MovementSpeed)>,
+) {
+    for (unit, mut buff, mut speed) in query.iter_mut() {
+        if buff.timer.tick(time.delta()).finished() {
+            speed.0 = buff.base_speed;
+            commands.entity(unit).remove::<SpeedBuff>();
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use crate::components::*;
+use crate::events::NotEnoughEnergyEvent;
+use bevy::prelude::*;
+
+/// Scales the red health bar and updates the health text
+/// from the player's health.
+pub fn health_hud_system(
+    player_query: Query<(&Health, &MaxHealth), (With<Player>, Changed<Health>)>,
+    mut bar_query: Query<&mut Style, With<HealthBar>>,
+    mut text_query: Query<&mut Text, With<HealthText>>,
+) {
+    if let Ok((health, max_health)) = player_query.single() {
+        if let Ok(mut style) = bar_query.single_mut() {
+            style.size.width = Val::Percent(fill_percent(health.0, max_health.0));
+        }
+        if let Ok(mut text) = text_query.single_mut() {
+            text.sections[0].value = format!("{} / {}", health.0, max_health.0);
+        }
+    }
+}
+
+/// Scales the gold energy bar and updates the energy text
+/// from the player's energy.
+pub fn energy_hud_system(
+    player_query: Query<(&Energy, &MaxEnergy), (With<Player>, Changed<Energy>)>,
+    mut bar_query: Query<&mut Style, With<EnergyBar>>,
+    mut text_query: Query<&mut Text, With<EnergyText>>,
+) {
+    if let Ok((energy, max_energy)) = player_query.single() {
+        if let Ok(mut style) = bar_query.single_mut() {
+            style.size.width = Val::Percent(fill_percent(energy.0, max_energy.0));
+        }
+        if let Ok(mut text) = text_query.single_mut() {
+            text.sections[0].value = format!("{} / {}", energy.0, max_energy.0);
+        }
+    }
+}
+
+/// Flashes the energy bar red for a moment when the player
+/// tries to use an ability without enough energy.
+pub fn energy_feedback_system(
+    time: Res<Time>,
+    mut not_enough_energy: EventReader<NotEnoughEnergyEvent>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    player_query: Query<Entity, With<Player>>,
+    bar_query: Query<&Handle<ColorMaterial>, With<EnergyBar>>,
+    mut flash: Local<Timer>,
+) {
+    if let Ok(player) = player_query.single() {
+        if not_enough_energy.iter().any(|event| event.unit == player) {
+            *flash = Timer::from_seconds(0.4, false);
+        }
+    }
+    flash.tick(time.delta());
+
+    let color = if flash.finished() {
+        Color::GOLD
+    } else {
+        Color::RED
+    };
+    if let Ok(handle) = bar_query.single() {
+        // only touch the material when the color changes
+        if materials
+            .get(handle)
+            .map_or(false, |material| material.color != color)
+        {
+            if let Some(material) = materials.get_mut(handle) {
+                material.color = color;
+            }
+        }
+    }
+}
+
+/// Lights up one battery for each of the player's dash charges
+/// that is available.
+pub fn charge_battery_system(
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    player_query: Query<Entity, With<Player>>,
+    ability_query: Query<(&AbilityOwner, &CastAbility, &Charges)>,
+    battery_query: Query<(&ChargeBattery, &Handle<ColorMaterial>)>,
+) {
+    let player = match player_query.single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let dash = ability_query
+        .iter()
+        .find(|(owner, ability, _)| owner.0 == player && **ability == CastAbility::Dash);
+    if let Some((_, _, charges)) = dash {
+        for (battery, handle) in battery_query.iter() {
+            let color = if battery.0 < charges.0 {
+                Color::GOLD
+            } else {
+                Color::rgb(0.55, 0.55, 0.55)
+            };
+            // only touch the material when the color changes
+            if materials
+                .get(handle)
+                .map_or(false, |material| material.color != color)
+            {
+                if let Some(material) = materials.get_mut(handle) {
+                    material.color = color;
+                }
+            }
+        }
+    }
+}
+
+/// Covers the player's ability slots with an overlay whose height is the
+/// fraction of the cooldown left before the ability gains its next charge.
+pub fn cooldown_sweep_system(
+    player_query: Query<Entity, With<Player>>,
+    ability_query: Query<(
+        &AbilityOwner,
+        &CastAbility,
+        &Charges,
+        &MaxCharges,
+        &CooldownTimer,
+    )>,
+    mut sweep_query: Query<(&CooldownSweep, &mut Style)>,
+) {
+    let player = match player_query.single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    for (sweep, mut style) in sweep_query.iter_mut() {
+        let remaining = ability_query
+            .iter()
+            .find(|(owner, ability, ..)| owner.0 == player && **ability == sweep.0)
+            .map_or(0.0, |(_, _, charges, max_charges, timer)| {
+                if charges.0 < max_charges.0 {
+                    timer.0.percent_left()
+                } else {
+                    0.0
+                }
+            });
+        style.size.height = Val::Percent(100.0 * remaining);
+    }
+}
+
+fn fill_percent(value: i64, max: i64) -> f32 {
+    if max <= 0 {
+        0.0
+    } else {
+        100.0 * value.max(0).min(max) as f32 / max as f32
+    }
+}
@@ -1,44 +1,182 @@
 use crate::components::*;
 use crate::events::*;
+use crate::resources::{GamepadInput, KeybindMenu};
 use bevy::prelude::*;
 
-/// System that watches keyboard and mouse events and forwards them
-/// to the player action system.
+// Stick positions closer to the center than this count as at rest.
+const STICK_DEADZONE: f32 = 0.2;
+
+// How far in front of the player the right stick aims.
+const AIM_DISTANCE: f32 = 300.0;
+
+/// System that watches keyboard, mouse and gamepad events and forwards
+/// them to the player action system. Each binding sends its action on
+/// press, on release or the whole time it is held depending on its
+/// trigger. Mouse and keyboard actions are aimed at the cursor, gamepad
+/// actions at the right stick's aim point. Actions sent while shift is
+/// held are queued. Input is ignored while the keybinding menu is open,
+/// apart from releasing inputs that were already held.
+#[allow(clippy::too_many_arguments)]
 pub fn input_system(
+    menu: Res<KeybindMenu>,
     wnds: Res<Windows>,
     q_camera: Query<&Transform, With<MainCamera>>,
+    q_player: Query<&Transform, With<Player>>,
     mouse_input: Res<Input<MouseButton>>,
     keyboard_input: Res<Input<KeyCode>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    gamepad: Res<GamepadInput>,
     mut player_action: EventWriter<PlayerAction>,
     q_controls: Query<&UserControls>,
+    mut last_cursor: Local<Option<Vec3>>,
 ) {
-    let wnd = wnds.get_primary().unwrap();
-    if let Some(pos) = wnd.cursor_position() {
-        let size = Vec2::new(wnd.width() as f32, wnd.height() as f32);
+    // without a window or camera there is no cursor to aim with
+    let mouse_world_coordinates = match (wnds.get_primary(), q_camera.single()) {
+        (Some(wnd), Ok(camera_transform)) => wnd.cursor_position().map(|pos| {
+            let size = Vec2::new(wnd.width() as f32, wnd.height() as f32);
+
+            // the default orthographic projection is in pixels from the center;
+            // just undo the translation
+            let p = pos - size / 2.0;
 
-        // the default orthographic projection is in pixels from the center;
-        // just undo the translation
-        let p = pos - size / 2.0;
+            // apply the camera transform, result is the `world coords` of the mouse
+            let pos_wld = camera_transform.compute_matrix() * p.extend(0.0).extend(1.0);
+            Vec3::new(pos_wld.x, pos_wld.y, 1.0)
+        }),
+        _ => None,
+    };
+    if mouse_world_coordinates.is_some() {
+        *last_cursor = mouse_world_coordinates;
+    }
 
-        // assuming there is exactly one main camera entity, so this is OK
-        let camera_transform = q_camera.single().unwrap();
+    let aim_coordinates = q_player.single().ok().map(|transform| {
+        let aim = transform.translation.truncate() + gamepad.aim * AIM_DISTANCE;
+        aim.extend(1.0)
+    });
 
-        // apply the camera transform, result is the `world coords` of the mouse
-        let pos_wld = camera_transform.compute_matrix() * p.extend(0.0).extend(1.0);
-        let mouse_world_coordinates = Vec3::new(pos_wld.x, pos_wld.y, 1.0);
+    let queue_modifier =
+        keyboard_input.pressed(KeyCode::LShift) || keyboard_input.pressed(KeyCode::RShift);
 
-        if let Ok(controls) = q_controls.single() {
-            for mouse_button in mouse_input.get_pressed() {
-                if let Some(action) = controls.mouse.get(mouse_button) {
-                    player_action.send(PlayerAction::new(*action, mouse_world_coordinates));
-                }
+    if let Ok(controls) = q_controls.single() {
+        let mouse = controls.mouse.iter().map(|(button, binding)| {
+            (
+                binding,
+                mouse_world_coordinates,
+                mouse_input.just_pressed(*button),
+                mouse_input.pressed(*button),
+                mouse_input.just_released(*button),
+            )
+        });
+        let keyboard = controls.keyboard.iter().map(|(key, binding)| {
+            (
+                binding,
+                mouse_world_coordinates,
+                keyboard_input.just_pressed(*key),
+                keyboard_input.pressed(*key),
+                keyboard_input.just_released(*key),
+            )
+        });
+        let pad = gamepad
+            .active
+            .map(|pad| {
+                controls.gamepad.iter().map(move |(button_type, binding)| {
+                    let button = GamepadButton(pad, *button_type);
+                    (
+                        binding,
+                        aim_coordinates,
+                        gamepad_input.just_pressed(button),
+                        gamepad_input.pressed(button),
+                        gamepad_input.just_released(button),
+                    )
+                })
+            })
+            .into_iter()
+            .flatten();
+
+        for (binding, coords, just_pressed, pressed, just_released) in
+            mouse.chain(keyboard).chain(pad)
+        {
+            let phase = match binding.trigger {
+                Trigger::Press if just_pressed => ActionPhase::Started,
+                Trigger::Press if just_released => ActionPhase::Ended,
+                Trigger::Release if just_released => ActionPhase::Started,
+                Trigger::Held if just_pressed => ActionPhase::Started,
+                Trigger::Held if pressed => ActionPhase::Held,
+                Trigger::Held if just_released => ActionPhase::Ended,
+                _ => continue,
+            };
+
+            if menu.open && phase != ActionPhase::Ended {
+                continue;
             }
 
-            for key in keyboard_input.get_pressed() {
-                if let Some(action) = controls.keyboard.get(key) {
-                    player_action.send(PlayerAction::new(*action, mouse_world_coordinates));
+            // actions need somewhere to aim, except for releasing an input
+            // which still has to go through when the cursor is outside of
+            // the window. It is aimed where the cursor was last seen.
+            let coords = match (coords, phase) {
+                (Some(coords), _) => coords,
+                (None, ActionPhase::Ended) => last_cursor.unwrap_or(Vec3::ZERO),
+                (None, _) => continue,
+            };
+
+            let mut action = PlayerAction::new(binding.action, phase, coords);
+            action.queued = queue_modifier;
+            player_action.send(action);
+        }
+    }
+}
+
+/// System that keeps track of the gamepad the player is using and
+/// reads its sticks. The first gamepad to connect is used until it
+/// disconnects, or until a button is pressed on another gamepad.
+pub fn gamepad_system(
+    mut gamepad_events: EventReader<GamepadEvent>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut gamepad: ResMut<GamepadInput>,
+) {
+    for GamepadEvent(pad, event_type) in gamepad_events.iter() {
+        match event_type {
+            GamepadEventType::Connected => {
+                if gamepad.active.is_none() {
+                    gamepad.active = Some(*pad);
                 }
             }
+            GamepadEventType::Disconnected => {
+                if gamepad.active == Some(*pad) {
+                    gamepad.active = None;
+                }
+            }
+            GamepadEventType::ButtonChanged(..) => gamepad.active = Some(*pad),
+            GamepadEventType::AxisChanged(..) => (),
+        }
+    }
+
+    let pad = match gamepad.active {
+        Some(pad) => pad,
+        None => {
+            gamepad.movement = Vec2::ZERO;
+            return;
         }
+    };
+
+    let stick = |x, y| {
+        let stick = Vec2::new(
+            axes.get(GamepadAxis(pad, x)).unwrap_or(0.0),
+            axes.get(GamepadAxis(pad, y)).unwrap_or(0.0),
+        );
+        if stick.length() < STICK_DEADZONE {
+            Vec2::ZERO
+        } else {
+            stick
+        }
+    };
+    let movement = stick(GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY);
+    let aim = stick(GamepadAxisType::RightStickX, GamepadAxisType::RightStickY);
+
+    gamepad.movement = movement;
+    if aim != Vec2::ZERO {
+        gamepad.aim = aim.normalize();
+    } else if movement != Vec2::ZERO {
+        gamepad.aim = movement.normalize();
     }
 }
@@ -0,0 +1,32 @@
+use crate::components::SimPosition;
+use crate::resources::SimulationTime;
+use bevy::prelude::*;
+
+/// System that starts a tick by putting units back where the last tick
+/// left them, undoing the interpolation that was drawn since.
+pub fn restore_position_system(mut query: Query<(&mut SimPosition, &mut Transform)>) {
+    for (mut position, mut transform) in query.iter_mut() {
+        position.previous = position.current;
+        transform.translation = position.current;
+    }
+}
+
+/// System that ends a tick by remembering where every unit ended up.
+pub fn record_position_system(mut query: Query<(&mut SimPosition, &Transform)>) {
+    for (mut position, transform) in query.iter_mut() {
+        position.current = transform.translation;
+    }
+}
+
+/// System that draws units between where the last two ticks left them,
+/// by how far the frame is into the next tick. Units move smoothly even
+/// when the frame rate doesn't match the tick rate.
+pub fn interpolation_system(
+    sim_time: Res<SimulationTime>,
+    mut query: Query<(&SimPosition, &mut Transform)>,
+) {
+    let alpha = sim_time.overstep().min(1.0);
+    for (position, mut transform) in query.iter_mut() {
+        transform.translation = position.previous.lerp(position.current, alpha);
+    }
+}
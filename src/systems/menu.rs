@@ -0,0 +1,281 @@
+use crate::components::*;
+use crate::config;
+use crate::events::Action;
+use crate::resources::KeybindMenu;
+use bevy::prelude::*;
+
+enum NewBinding {
+    Mouse(MouseButton),
+    Key(KeyCode),
+    Gamepad(GamepadButtonType),
+}
+
+/// Opens and closes the keybinding menu with Escape. While the menu
+/// is waiting for a new binding Escape cancels the rebind instead.
+pub fn toggle_menu_system(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    asset_server: Res<AssetServer>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut menu: ResMut<KeybindMenu>,
+    root_query: Query<Entity, With<KeybindMenuRoot>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::Escape) {
+        return;
+    }
+
+    if menu.rebinding.is_some() {
+        menu.rebinding = None;
+        menu.message.clear();
+        return;
+    }
+
+    menu.open = !menu.open;
+    if menu.open {
+        spawn_menu(&mut commands, &mut materials, &asset_server);
+    } else {
+        menu.message.clear();
+        for root in root_query.iter() {
+            commands.entity(root).despawn_recursive();
+        }
+    }
+}
+
+/// Clicking a row in the menu waits for a new binding for that action.
+pub fn select_binding_system(
+    mut menu: ResMut<KeybindMenu>,
+    query: Query<(&Interaction, &BindingRow), Changed<Interaction>>,
+) {
+    for (interaction, row) in query.iter() {
+        if *interaction == Interaction::Clicked {
+            menu.rebinding = Some(row.0);
+            menu.armed = false;
+            menu.message = format!(
+                "Press a key or mouse button for {} (Esc to cancel)",
+                row.0.name()
+            );
+        }
+    }
+}
+
+/// Binds the next key, mouse button or gamepad button that is pressed
+/// to the action waiting for a new binding. Inputs that are already
+/// bound to another action are refused. Gamepad buttons replace the
+/// gamepad bindings of the action and keys or mouse buttons replace
+/// the others. The new controls are saved to the config file.
+pub fn rebind_system(
+    mut menu: ResMut<KeybindMenu>,
+    mouse_input: Res<Input<MouseButton>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    mut q_controls: Query<&mut UserControls>,
+) {
+    let action = match menu.rebinding {
+        Some(action) => action,
+        None => return,
+    };
+
+    if !menu.armed {
+        menu.armed = mouse_input.get_pressed().next().is_none();
+        return;
+    }
+
+    // Escape is reserved for cancelling
+    let new_binding = keyboard_input
+        .get_just_pressed()
+        .find(|key| **key != KeyCode::Escape)
+        .map(|key| NewBinding::Key(*key))
+        .or_else(|| {
+            mouse_input
+                .get_just_pressed()
+                .next()
+                .map(|button| NewBinding::Mouse(*button))
+        })
+        .or_else(|| {
+            gamepad_input
+                .get_just_pressed()
+                .next()
+                .map(|button| NewBinding::Gamepad(button.1))
+        });
+
+    if let (Some(new_binding), Ok(mut controls)) = (new_binding, q_controls.single_mut()) {
+        let (name, bound) = match new_binding {
+            NewBinding::Mouse(button) => (
+                format!("Mouse {:?}", button),
+                controls.mouse.get(&button).copied(),
+            ),
+            NewBinding::Key(key) => (format!("{:?}", key), controls.keyboard.get(&key).copied()),
+            NewBinding::Gamepad(button) => (
+                format!("Pad {:?}", button),
+                controls.gamepad.get(&button).copied(),
+            ),
+        };
+
+        if let Some(other) = bound {
+            if other.action != action {
+                menu.message = format!(
+                    "{} is already bound to {}, pick another input (Esc to cancel)",
+                    name,
+                    other.action.name()
+                );
+                return;
+            }
+        }
+
+        let binding = Binding {
+            action,
+            trigger: controls.trigger(action),
+        };
+        match new_binding {
+            NewBinding::Mouse(button) => {
+                controls.unbind_mouse_and_keyboard(action);
+                controls.mouse.insert(button, binding);
+            }
+            NewBinding::Key(key) => {
+                controls.unbind_mouse_and_keyboard(action);
+                controls.keyboard.insert(key, binding);
+            }
+            NewBinding::Gamepad(button) => {
+                controls.unbind_gamepad(action);
+                controls.gamepad.insert(button, binding);
+            }
+        }
+
+        menu.rebinding = None;
+        menu.message = match config::save_controls(&controls) {
+            Ok(()) => format!("{} bound to {}", action.name(), name),
+            Err(err) => format!("{} bound to {} but not saved: {}", action.name(), name, err),
+        };
+    }
+}
+
+/// Keeps the text of every row and the feedback message up to date.
+pub fn menu_text_system(
+    menu: Res<KeybindMenu>,
+    q_controls: Query<&UserControls>,
+    mut row_query: Query<(&BindingRowText, &mut Text)>,
+    mut message_query: Query<&mut Text, (With<MenuMessage>, Without<BindingRowText>)>,
+) {
+    if !menu.open {
+        return;
+    }
+
+    if let Ok(controls) = q_controls.single() {
+        for (row, mut text) in row_query.iter_mut() {
+            let bindings = if menu.rebinding == Some(row.0) {
+                "...".to_string()
+            } else {
+                let bindings = controls.bindings(row.0);
+                if bindings.is_empty() {
+                    "unbound".to_string()
+                } else {
+                    bindings.join(", ")
+                }
+            };
+            let value = format!("{}: {}", row.0.name(), bindings);
+            if text.sections[0].value != value {
+                text.sections[0].value = value;
+            }
+        }
+    }
+
+    if let Ok(mut text) = message_query.single_mut() {
+        if text.sections[0].value != menu.message {
+            text.sections[0].value = menu.message.clone();
+        }
+    }
+}
+
+fn spawn_menu(
+    commands: &mut Commands,
+    materials: &mut ResMut<Assets<ColorMaterial>>,
+    asset_server: &Res<AssetServer>,
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let text_style = TextStyle {
+        font,
+        font_size: 30.0,
+        color: Color::WHITE,
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Percent(30.0),
+                    top: Val::Percent(15.0),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Percent(40.0), Val::Auto),
+                padding: Rect::all(Val::Px(10.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Stretch,
+                ..Default::default()
+            },
+            material: materials.add(Color::rgba(0.15, 0.15, 0.15, 0.95).into()),
+            ..Default::default()
+        })
+        .insert(KeybindMenuRoot)
+        .with_children(|parent| {
+            // title
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Px(5.0)),
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    "Controls (Esc to close)",
+                    text_style.clone(),
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+
+            // one row per action, click to rebind
+            for action in Action::ALL.iter() {
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            margin: Rect::all(Val::Px(5.0)),
+                            padding: Rect::all(Val::Px(5.0)),
+                            ..Default::default()
+                        },
+                        material: materials.add(Color::rgb(0.35, 0.35, 0.35).into()),
+                        ..Default::default()
+                    })
+                    .insert(BindingRow(*action))
+                    .with_children(|parent| {
+                        parent
+                            .spawn_bundle(TextBundle {
+                                text: Text::with_section(
+                                    action.name(),
+                                    text_style.clone(),
+                                    Default::default(),
+                                ),
+                                ..Default::default()
+                            })
+                            .insert(BindingRowText(*action));
+                    });
+            }
+
+            // feedback
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(5.0)),
+                        ..Default::default()
+                    },
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font_size: 20.0,
+                            ..text_style.clone()
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(MenuMessage);
+        });
+}
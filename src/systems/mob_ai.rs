@@ -0,0 +1,133 @@
+use crate::components::*;
+use crate::events::*;
+use crate::resources::SimulationTime;
+use bevy::prelude::*;
+
+/// System that decides what each mob does next.
+///
+/// Mobs sit idle until the player comes within their aggro radius,
+/// then chase the player using the normal `Moving` state so the
+/// movement system paths them through the `TileGraph`. Mobs with a
+/// `Shoot` ability fire it at the player on the way whenever it has a
+/// charge and the player is in range. Once the player is within attack
+/// range the mob stops and winds up an attack, which only lands if the
+/// player is still close by when the wind-up finishes.
+///
+/// If a mob gets pulled further than its leash distance from its
+/// spawn point (or loses the player) it walks back home and ignores
+/// the player until it gets there.
+pub fn mob_brain_system(
+    time: Res<SimulationTime>,
+    mut damage_events: EventWriter<DamageEvent>,
+    player_query: Query<(Entity, &Transform, &CharState), (With<Player>, Without<Mob>)>,
+    ability_query: Query<(&AbilityOwner, &CastAbility, &Charges, &CastRange)>,
+    mut mob_query: Query<
+        (
+            Entity,
+            &mut MobBrain,
+            &mut AttackTimer,
+            &mut CharState,
+            &Transform,
+            &SpawnPoint,
+        ),
+        With<Mob>,
+    >,
+) {
+    // how close a mob has to get to its spawn point to stop leashing
+    let home_radius = 25.0;
+    // how far the player can move from a mob's destination before it repaths
+    let repath_distance = 50.0;
+
+    // a dead player can't be chased
+    let player = player_query
+        .single()
+        .ok()
+        .filter(|(_, _, state)| **state != CharState::Dead)
+        .map(|(entity, transform, _)| (entity, transform.translation));
+
+    for (mob, mut brain, mut attack_timer, mut state, transform, spawn_point) in
+        mob_query.iter_mut()
+    {
+        if *state == CharState::Dead {
+            continue;
+        }
+
+        let position = transform.translation;
+        let home_distance = position.truncate().distance(spawn_point.0.truncate());
+
+        if brain.leashing {
+            if home_distance < home_radius {
+                brain.leashing = false;
+                *state = CharState::Idle;
+            } else if !matches!(*state, CharState::Moving(..)) {
+                *state = CharState::Moving(Coords(spawn_point.0), None);
+            }
+            continue;
+        }
+
+        if home_distance > brain.leash_distance {
+            brain.leashing = true;
+            *state = CharState::Moving(Coords(spawn_point.0), None);
+            continue;
+        }
+
+        let target = player.map(|(entity, player_position)| {
+            (
+                entity,
+                player_position,
+                position.truncate().distance(player_position.truncate()),
+            )
+        });
+
+        match *state {
+            // the cast system is done with casts once they go off
+            CharState::Casting(_) => (),
+            CharState::Attacking(_) => {
+                if attack_timer.0.tick(time.delta()).finished() {
+                    // the attack only lands if the player didn't get away
+                    // during the wind-up
+                    if let Some((entity, _, distance)) = target {
+                        if distance <= brain.attack_range {
+                            damage_events.send(DamageEvent {
+                                target: entity,
+                                amount: brain.attack_damage,
+                            });
+                        }
+                    }
+                    *state = CharState::Idle;
+                }
+            }
+            _ => match target {
+                Some((entity, _, distance)) if distance <= brain.attack_range => {
+                    attack_timer.0.reset();
+                    *state = CharState::Attacking(entity);
+                }
+                Some((_, player_position, distance)) if distance <= brain.aggro_radius => {
+                    let shot_ready = ability_query.iter().any(|(owner, class, charges, range)| {
+                        owner.0 == mob
+                            && *class == CastAbility::Shoot
+                            && charges.0 > 0
+                            && distance <= range.0
+                    });
+                    if shot_ready {
+                        *state = CharState::Casting((CastAbility::Shoot, Coords(player_position)));
+                        continue;
+                    }
+                    if let CharState::Moving(destination, _) = *state {
+                        if (destination.0 - player_position).length() < repath_distance {
+                            continue;
+                        }
+                    }
+                    *state = CharState::Moving(Coords(player_position), None);
+                }
+                // lost the player so head back home
+                _ => {
+                    if matches!(*state, CharState::Moving(..)) {
+                        brain.leashing = true;
+                        *state = CharState::Moving(Coords(spawn_point.0), None);
+                    }
+                }
+            },
+        }
+    }
+}
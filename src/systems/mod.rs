@@ -1,5 +1,14 @@
 pub mod abilities;
+pub mod combat;
+pub mod effects;
+pub mod hud;
 pub mod input;
+pub mod interpolation;
+pub mod menu;
+pub mod mob_ai;
 pub mod movement;
 pub mod player_action;
+pub mod replay;
 pub mod setup;
+pub mod targeting;
+pub mod time;
@@ -1,6 +1,8 @@
-use crate::components::{CharState, MovementSpeed};
+use crate::components::{CharState, CommandQueue, MovementSpeed, Player};
+use crate::resources::{GamepadInput, SimulationTime};
 use bevy::prelude::*;
 use petgraph::graphmap::UnGraphMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use std::collections::VecDeque;
 
 /// System that moves the player and mobs. For anything that has
@@ -9,17 +11,16 @@ use std::collections::VecDeque;
 /// Uses the TileGraph struct to calculate paths if needed. If a
 /// path exists, this system also validates that path before moving.
 ///
-/// Because this system moves units it creates and maintains a data
-/// structure to track which tiles are impassable for the pathing
-/// function.
+/// Because this system moves units it updates the tiles they occupy
+/// in the TileGraph as they cross into new tiles.
 pub fn movement_system(
-    time: Res<Time>,
-    mut query: Query<(&mut CharState, &MovementSpeed, &mut Transform)>,
+    time: Res<SimulationTime>,
+    mut query: Query<(Entity, &mut CharState, &MovementSpeed, &mut Transform)>,
     mut q_graph: Query<&mut TileGraph>,
 ) {
     let delta_seconds = time.delta_seconds();
     if let Ok(mut graph) = q_graph.single_mut() {
-        for (mut state, speed, mut transform) in query.iter_mut() {
+        for (unit, mut state, speed, mut transform) in query.iter_mut() {
             match *state {
                 // units that have entered moving state but no path
                 // has been calculated yet
@@ -30,7 +31,7 @@ pub fn movement_system(
                     ) {
                         // update the state to have the path we chose
                         *state = CharState::Moving(destination, Some(path));
-                        graph.move_char(&mut transform, speed.0, delta_seconds, &mut state);
+                        graph.move_char(unit, &mut transform, speed.0, delta_seconds, &mut state);
                     } else {
                         *state = CharState::Idle;
                     }
@@ -49,7 +50,7 @@ pub fn movement_system(
                         }
                     }
 
-                    graph.move_char(&mut transform, speed.0, delta_seconds, &mut state);
+                    graph.move_char(unit, &mut transform, speed.0, delta_seconds, &mut state);
                 }
                 // all other character states can be ignored.
                 // potential optimazation could be to alter
@@ -61,14 +62,99 @@ pub fn movement_system(
     }
 }
 
+/// System that moves the player along the gamepad's left stick.
+///
+/// Tilting the stick cancels any path the player was following and,
+/// like any other move that isn't queued, the player's queued commands.
+/// Casts, channels and attacks are left alone. The player slides along
+/// walls and other units instead of walking into tiles they can't enter.
+pub fn direct_movement_system(
+    time: Res<SimulationTime>,
+    gamepad: Res<GamepadInput>,
+    mut query: Query<
+        (
+            Entity,
+            &mut CharState,
+            &mut CommandQueue,
+            &MovementSpeed,
+            &mut Transform,
+        ),
+        With<Player>,
+    >,
+    mut q_graph: Query<&mut TileGraph>,
+) {
+    if gamepad.movement == Vec2::ZERO {
+        return;
+    }
+
+    if let (Ok(mut graph), Ok((unit, mut state, mut queue, speed, mut transform))) =
+        (q_graph.single_mut(), query.single_mut())
+    {
+        match *state {
+            CharState::Idle => (),
+            CharState::Moving(..) => *state = CharState::Idle,
+            _ => return,
+        }
+        if !queue.0.is_empty() {
+            queue.0.clear();
+        }
+
+        // the stick's tilt scales the speed
+        let step = gamepad.movement.clamp_length_max(1.0) * speed.0 * time.delta_seconds();
+        let position = transform.translation.truncate();
+        let candidates = [
+            position + step,
+            position + Vec2::new(step.x, 0.0),
+            position + Vec2::new(0.0, step.y),
+        ];
+
+        if let Some(new_position) = candidates
+            .iter()
+            .find(|point| graph.can_enter(unit, graph.world_to_cube((point.x, point.y))))
+        {
+            transform.translation.x = new_position.x;
+            transform.translation.y = new_position.y;
+            let tile = graph.world_to_cube((new_position.x, new_position.y));
+            graph.occupy(unit, tile);
+        }
+    }
+}
+
+/// System that keeps the tile occupancy in the TileGraph authoritative.
+///
+/// Every living unit is put in the tile it is standing in, which covers
+/// spawns, dashes, respawns and any other teleport that doesn't go through
+/// the movement system. Dead units and units that no longer exist are
+/// removed so their old tiles can't stay occupied.
+pub fn occupancy_system(
+    mut q_graph: Query<&mut TileGraph>,
+    query: Query<(Entity, &Transform, &CharState)>,
+) {
+    if let Ok(mut graph) = q_graph.single_mut() {
+        let mut living = FxHashSet::default();
+        for (unit, transform, state) in query.iter() {
+            if *state != CharState::Dead {
+                let tile = graph.world_to_cube((transform.translation.x, transform.translation.y));
+                graph.occupy(unit, tile);
+                living.insert(unit);
+            }
+        }
+        graph.retain_units(&living);
+    }
+}
+
 pub struct TileGraph {
     // TileGraph is for keeping track of every tile that *can*
     // be occupied. Impassable tiles are not stored here.
     graph: UnGraphMap<(i32, i32, i32), ()>,
 
-    // Keeps track of which tiles have units (player or mobs) in them.
-    // This way when we calculate a path we can traverse only empty tiles.
-    occupied_tiles: std::collections::HashSet<(i32, i32, i32)>,
+    // Keeps track of which tile each unit (player or mob) is in. Keyed
+    // by entity so a unit can only ever occupy a single tile.
+    unit_tiles: FxHashMap<Entity, (i32, i32, i32)>,
+
+    // Keeps track of how many units are in each occupied tile. This way
+    // when we calculate a path we can traverse only empty tiles.
+    occupied_tiles: FxHashMap<(i32, i32, i32), u32>,
 
     // How large each tile is. Length from center to vertex of hexagon (radius).
     cell_size: f32,
@@ -90,33 +176,36 @@ pub struct TileGraph {
 }
 
 impl TileGraph {
-    pub fn new(map_size: i32, cell_size: f32) -> Self {
+    // Builds the graph out of every passable tile on the map.
+    pub fn new(tiles: &[(i32, i32, i32)], cell_size: f32) -> Self {
         let mut graph = UnGraphMap::new();
-        let occupied_tiles = std::collections::HashSet::new();
-
-        // To make a full TileGraph using cubic coordinates,
-        // (x, y, z) for each tile, x + y + z == 0 must be
-        // satisfied to be a valid tile coordinate
-        let tiles = (-map_size..=map_size)
-            .map(|x| (-map_size..=map_size).map(move |y| (x, y)))
-            .flatten()
-            .map(|(x, y)| (-map_size..=map_size).map(move |z| (x, y, z)))
-            .flatten()
-            .filter(|coords| coords.0 + coords.1 + coords.2 == 0);
+        let unit_tiles = FxHashMap::default();
+        let occupied_tiles = FxHashMap::default();
 
+        // For valid cubic coordinates, (x, y, z) for each
+        // tile, x + y + z == 0 must be satisfied
         for tile in tiles {
-            graph.add_node(tile);
+            graph.add_node(*tile);
         }
 
         let nodes: Vec<(i32, i32, i32)> = graph.nodes().collect();
         // adds every edge two times but w/e it's fine
         for (x, y, z) in nodes {
-            graph.add_edge((x, y, z), (x + 1, y - 1, z), ());
-            graph.add_edge((x, y, z), (x + 1, y, z - 1), ());
-            graph.add_edge((x, y, z), (x, y + 1, z - 1), ());
-            graph.add_edge((x, y, z), (x - 1, y + 1, z), ());
-            graph.add_edge((x, y, z), (x - 1, y, z + 1), ());
-            graph.add_edge((x, y, z), (x, y - 1, z + 1), ());
+            let neighbors = [
+                (x + 1, y - 1, z),
+                (x + 1, y, z - 1),
+                (x, y + 1, z - 1),
+                (x - 1, y + 1, z),
+                (x - 1, y, z + 1),
+                (x, y - 1, z + 1),
+            ];
+            // only connect to tiles that are on the map, otherwise
+            // add_edge would create the missing node
+            for neighbor in neighbors.iter() {
+                if graph.contains_node(*neighbor) {
+                    graph.add_edge((x, y, z), *neighbor, ());
+                }
+            }
         }
 
         // https://www.redblobgames.com/grids/hexagons/#hex-to-pixel
@@ -130,6 +219,7 @@ impl TileGraph {
 
         Self {
             graph,
+            unit_tiles,
             occupied_tiles,
             cell_size,
             axial_to_world_transformation,
@@ -141,13 +231,12 @@ impl TileGraph {
     /* Conversions between coordinate systems that are useful         */
     /******************************************************************/
     pub fn world_to_cube(&self, coords: (f32, f32)) -> (i32, i32, i32) {
-        TileGraph::axial_to_cube(self.world_to_axial(coords))
+        let axial =
+            self.world_to_axial_transformation * Vec2::new(coords.0, coords.1) / self.cell_size;
+        TileGraph::cube_round((axial.x, -axial.x - axial.y, axial.y))
     }
     pub fn world_to_axial(&self, coords: (f32, f32)) -> (i32, i32) {
-        let vec = (self.world_to_axial_transformation * Vec2::new(coords.0, coords.1)
-            / self.cell_size)
-            .round();
-        (vec.x as i32, vec.y as i32)
+        TileGraph::cube_to_axial(self.world_to_cube(coords))
     }
     pub fn cube_to_world(&self, coords: (i32, i32, i32)) -> (f32, f32) {
         self.axial_to_world(TileGraph::cube_to_axial(coords))
@@ -165,31 +254,100 @@ impl TileGraph {
         (coords.0, -coords.0 - coords.1, coords.1)
     }
 
+    // Rounding each axis on its own can break x + y + z == 0 near the
+    // corners of a tile, so the axis that was rounded the furthest is
+    // recomputed from the other two.
+    // https://www.redblobgames.com/grids/hexagons/#rounding
+    pub fn cube_round(coords: (f32, f32, f32)) -> (i32, i32, i32) {
+        let (x, y, z) = coords;
+        let (mut rx, mut ry, mut rz) = (x.round(), y.round(), z.round());
+        let (dx, dy, dz) = ((rx - x).abs(), (ry - y).abs(), (rz - z).abs());
+
+        if dx > dy && dx > dz {
+            rx = -ry - rz;
+        } else if dy > dz {
+            ry = -rx - rz;
+        } else {
+            rz = -rx - ry;
+        }
+
+        (rx as i32, ry as i32, rz as i32)
+    }
+
+    // Number of steps between two tiles on an open map.
+    pub fn hex_distance(a: (i32, i32, i32), b: (i32, i32, i32)) -> i32 {
+        ((a.0 - b.0).abs() + (a.1 - b.1).abs() + (a.2 - b.2).abs()) / 2
+    }
+
     // Args: the world coordinates of a unit location (start) and the world coordinates
     // of where the unit is trying to move to (end).
-    // Returns: A list of tile indices that the unit can traverse to get there.
+    // Returns: A list of tile indices that the unit can traverse to get there. If the
+    // end is off the map, impassable or cut off from the start then the path leads to
+    // the reachable tile closest to the end instead. None if the start isn't on the map.
     pub fn path(&self, start: (f32, f32), end: (f32, f32)) -> Option<VecDeque<(i32, i32, i32)>> {
-        let end = self.world_to_cube(end);
-        if let Some((_, path)) = petgraph::algo::astar(
+        let start_tile = self.world_to_cube(start);
+        if !self.graph.contains_node(start_tile) {
+            return None;
+        }
+
+        let end_tile = self.world_to_cube(end);
+        let path = if self.graph.contains_node(end_tile) {
+            self.astar(start_tile, end_tile)
+        } else {
+            None
+        };
+
+        path.or_else(|| {
+            let closest = self.closest_reachable(start_tile, end);
+            self.astar(start_tile, closest)
+        })
+    }
+
+    fn astar(
+        &self,
+        start: (i32, i32, i32),
+        end: (i32, i32, i32),
+    ) -> Option<VecDeque<(i32, i32, i32)>> {
+        petgraph::algo::astar(
             &self.graph,
-            self.world_to_cube(start),
+            start,
             |target| target == end,
-            |(_, tile, _)| {
-                if self.occupied_tiles.contains(&tile) {
-                    10000
-                } else {
-                    1
-                }
-            },
-            |_| 0,
-        ) {
-            Some(path.into_iter().skip(1).collect())
+            |(_, tile, _)| self.tile_cost(tile),
+            // every step costs at least 1 so this never overestimates
+            |tile| TileGraph::hex_distance(tile, end),
+        )
+        .map(|(_, path)| path.into_iter().skip(1).collect())
+    }
+
+    // Of all the tiles that can be reached from start, the one whose center is
+    // closest to the target world coordinates. Ties go to the cheaper tile to reach.
+    fn closest_reachable(&self, start: (i32, i32, i32), target: (f32, f32)) -> (i32, i32, i32) {
+        let target = Vec2::new(target.0, target.1);
+        let distance = |tile: (i32, i32, i32)| {
+            let (x, y) = self.cube_to_world(tile);
+            Vec2::new(x, y).distance(target)
+        };
+
+        petgraph::algo::dijkstra(&self.graph, start, None, |(_, tile, _)| {
+            self.tile_cost(tile)
+        })
+        .into_iter()
+        .min_by(|(a, a_cost), (b, b_cost)| {
+            distance(*a)
+                .partial_cmp(&distance(*b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a_cost.cmp(b_cost))
+        })
+        .map_or(start, |(tile, _)| tile)
+    }
+
+    // Cost of stepping into a tile. Occupied tiles can be walked through
+    // but only if there is no other way around.
+    fn tile_cost(&self, tile: (i32, i32, i32)) -> i32 {
+        if self.is_occupied(tile) {
+            10000
         } else {
-            // TODO: Pathing note --- if there is no valid path then a* fails
-            // Right now we just return None when this happens but it would
-            // be better if the path that resulted in the closest endpoint
-            // to the desired location was produced.
-            None
+            1
         }
     }
 
@@ -197,16 +355,87 @@ impl TileGraph {
     // path is still valid (all tiles in the path remain unoccupied)
     pub fn validate(&self, char_state: &mut CharState) -> bool {
         if let CharState::Moving(_, Some(path)) = char_state {
-            path.iter().all(|step| !self.occupied_tiles.contains(step))
+            path.iter().all(|step| !self.is_occupied(*step))
         } else {
             false
         }
     }
 
+    // Tiles that are off the map or impassable are not in the graph.
+    pub fn is_passable(&self, tile: (i32, i32, i32)) -> bool {
+        self.graph.contains_node(tile)
+    }
+
+    // Args: the world coordinates a unit is teleporting from (start) and to (end).
+    // Returns: the end point if it is on a passable tile, otherwise the point
+    // closest to the end along the line back to the start that is passable.
+    // Falls back to the start if there are no passable points along the line.
+    pub fn landing_point(&self, start: Vec3, end: Vec3) -> Vec3 {
+        let line = end - start;
+        let step_size = self.cell_size / 2.0;
+        let steps = (line.length() / step_size).ceil() as i32;
+
+        (0..=steps)
+            .map(|step| end - line * (step as f32 / steps.max(1) as f32))
+            .find(|point| self.is_passable(self.world_to_cube((point.x, point.y))))
+            .unwrap_or(start)
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    pub fn is_occupied(&self, tile: (i32, i32, i32)) -> bool {
+        self.occupied_tiles.contains_key(&tile)
+    }
+
+    // Whether the unit can step into the tile, either because it is
+    // already standing there or because the tile is passable and empty.
+    pub fn can_enter(&self, unit: Entity, tile: (i32, i32, i32)) -> bool {
+        self.unit_tiles.get(&unit) == Some(&tile)
+            || (self.is_passable(tile) && !self.is_occupied(tile))
+    }
+
+    // Moves the unit into the given tile, freeing the tile it was in before.
+    pub fn occupy(&mut self, unit: Entity, tile: (i32, i32, i32)) {
+        if self.unit_tiles.get(&unit) == Some(&tile) {
+            return;
+        }
+        self.vacate(unit);
+        self.unit_tiles.insert(unit, tile);
+        *self.occupied_tiles.entry(tile).or_insert(0) += 1;
+    }
+
+    // Frees up the tile the unit is in, for example when the unit dies.
+    pub fn vacate(&mut self, unit: Entity) {
+        if let Some(tile) = self.unit_tiles.remove(&unit) {
+            if let Some(count) = self.occupied_tiles.get_mut(&tile) {
+                *count -= 1;
+                if *count == 0 {
+                    self.occupied_tiles.remove(&tile);
+                }
+            }
+        }
+    }
+
+    // Frees up the tiles of every unit that isn't in `units`.
+    pub fn retain_units(&mut self, units: &FxHashSet<Entity>) {
+        let stale: Vec<Entity> = self
+            .unit_tiles
+            .keys()
+            .filter(|unit| !units.contains(unit))
+            .copied()
+            .collect();
+        for unit in stale {
+            self.vacate(unit);
+        }
+    }
+
     // Updates the unit's sprite along the intended path. Path should have been
     // validated by the movement system before getting called.
     pub fn move_char(
         &mut self,
+        unit: Entity,
         char_transform: &mut Transform,
         move_speed: f32,
         delta_seconds: f32,
@@ -216,19 +445,15 @@ impl TileGraph {
             if let Some(path_step) = path.front() {
                 let (x, y) = self.cube_to_world(*path_step);
                 let direction = Vec3::new(x, y, 1.0) - char_transform.translation;
-                let old_x = char_transform.translation.x;
-                let old_y = char_transform.translation.y;
 
                 char_transform.translation += move_speed * delta_seconds * direction.normalize();
                 let new_x = char_transform.translation.x;
                 let new_y = char_transform.translation.y;
 
                 // if our new position is inside the tile we were moving to,
-                // pop that tile off the path queue and update the occupied_tiles
+                // pop that tile off the path queue and update the occupied tiles
                 if self.world_to_cube((new_x, new_y)) == *path_step {
-                    self.occupied_tiles
-                        .remove(&self.world_to_cube((old_x, old_y)));
-                    self.occupied_tiles.insert(*path_step);
+                    self.occupy(unit, *path_step);
                     path.pop_front();
                 }
             } else {
@@ -238,3 +463,201 @@ impl TileGraph {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::MapShape;
+
+    #[test]
+    fn world_cube_round_trip() {
+        let tiles = MapShape::Hexagon(10).tiles();
+        let graph = TileGraph::new(&tiles, 25.0);
+        for tile in tiles {
+            assert_eq!(graph.world_to_cube(graph.cube_to_world(tile)), tile);
+        }
+    }
+
+    #[test]
+    fn points_inside_a_tile_round_to_that_tile() {
+        let cell_size = 25.0;
+        let tiles = MapShape::Hexagon(3).tiles();
+        let graph = TileGraph::new(&tiles, cell_size);
+        // radius of the circle that fits inside a hexagon, just short of the edges
+        let inner_radius = 0.99 * cell_size * 3.0_f32.sqrt() / 2.0;
+        for tile in tiles {
+            let (x, y) = graph.cube_to_world(tile);
+            for step in 0..36 {
+                let angle = (step as f32 * 10.0).to_radians();
+                let point = (
+                    x + inner_radius * angle.cos(),
+                    y + inner_radius * angle.sin(),
+                );
+                assert_eq!(graph.world_to_cube(point), tile);
+            }
+        }
+    }
+
+    #[test]
+    fn open_map_paths_are_hex_distance_long() {
+        let tiles = MapShape::Hexagon(5).tiles();
+        let graph = TileGraph::new(&tiles, 25.0);
+        for start in tiles.iter() {
+            for end in tiles.iter() {
+                let path = graph
+                    .path(graph.cube_to_world(*start), graph.cube_to_world(*end))
+                    .unwrap();
+                assert_eq!(path.len() as i32, TileGraph::hex_distance(*start, *end));
+                assert_eq!(path.back().copied().unwrap_or(*start), *end);
+            }
+        }
+    }
+
+    // Of `reachable`, the tile whose center is closest to `target`.
+    fn nearest(graph: &TileGraph, reachable: &[(i32, i32, i32)], target: Vec2) -> (i32, i32, i32) {
+        let distance = |tile: &(i32, i32, i32)| {
+            let (x, y) = graph.cube_to_world(*tile);
+            Vec2::new(x, y).distance(target)
+        };
+        *reachable
+            .iter()
+            .min_by(|a, b| distance(a).partial_cmp(&distance(b)).unwrap())
+            .unwrap()
+    }
+
+    fn path_end(graph: &TileGraph, target: Vec2) -> (i32, i32, i32) {
+        let path = graph
+            .path(graph.cube_to_world((0, 0, 0)), (target.x, target.y))
+            .unwrap();
+        *path.back().unwrap()
+    }
+
+    #[test]
+    fn target_off_the_map_paths_to_the_nearest_tile() {
+        let tiles = MapShape::Hexagon(3).tiles();
+        let graph = TileGraph::new(&tiles, 25.0);
+        let (x, y) = graph.cube_to_world((6, -6, 0));
+        let target = Vec2::new(x, y);
+
+        assert!(!graph.is_passable(graph.world_to_cube((x, y))));
+        assert_eq!(path_end(&graph, target), nearest(&graph, &tiles, target));
+        assert_eq!(path_end(&graph, target), (3, -3, 0));
+    }
+
+    #[test]
+    fn impassable_target_paths_to_the_nearest_tile() {
+        let wall = (2, -2, 0);
+        let tiles: Vec<_> = MapShape::Hexagon(3)
+            .tiles()
+            .into_iter()
+            .filter(|tile| *tile != wall)
+            .collect();
+        let graph = TileGraph::new(&tiles, 25.0);
+        // inside the wall's tile, a little towards the start
+        let (x, y) = graph.cube_to_world(wall);
+        let target = Vec2::new(x, y) * (1.0 - 7.5 / Vec2::new(x, y).length());
+
+        assert_eq!(graph.world_to_cube((target.x, target.y)), wall);
+        assert_eq!(path_end(&graph, target), nearest(&graph, &tiles, target));
+        assert_eq!(path_end(&graph, target), (1, -1, 0));
+    }
+
+    #[test]
+    fn walled_off_target_paths_to_the_nearest_reachable_tile() {
+        // a ring of walls around the tiles next to the start
+        let tiles: Vec<_> = MapShape::Hexagon(4)
+            .tiles()
+            .into_iter()
+            .filter(|tile| TileGraph::hex_distance(*tile, (0, 0, 0)) != 2)
+            .collect();
+        let reachable: Vec<_> = tiles
+            .iter()
+            .copied()
+            .filter(|tile| TileGraph::hex_distance(*tile, (0, 0, 0)) < 2)
+            .collect();
+        let graph = TileGraph::new(&tiles, 25.0);
+        let (x, y) = graph.cube_to_world((4, -4, 0));
+        let target = Vec2::new(x, y);
+
+        assert!(graph.is_passable((4, -4, 0)));
+        assert_eq!(
+            path_end(&graph, target),
+            nearest(&graph, &reachable, target)
+        );
+        assert_eq!(path_end(&graph, target), (1, -1, 0));
+    }
+
+    #[test]
+    fn dash_into_a_wall_lands_on_a_passable_tile() {
+        let wall = (3, -3, 0);
+        let tiles: Vec<_> = MapShape::Hexagon(4)
+            .tiles()
+            .into_iter()
+            .filter(|tile| *tile != wall)
+            .collect();
+        let graph = TileGraph::new(&tiles, 25.0);
+        let start = Vec3::new(0.0, 0.0, 1.0);
+        let (x, y) = graph.cube_to_world(wall);
+        let end = Vec3::new(x, y, 1.0);
+
+        let landing = graph.landing_point(start, end);
+        assert!(graph.is_passable(graph.world_to_cube((landing.x, landing.y))));
+        // pulled back along the dash, but no further than it has to be
+        assert!(
+            landing
+                .truncate()
+                .normalize()
+                .distance(end.truncate().normalize())
+                < 1e-4
+        );
+        assert!(landing.distance(end) <= graph.cell_size() * 3.0_f32.sqrt());
+        assert_eq!(landing.z, end.z);
+    }
+
+    // World with an open map and one idle unit standing on the given
+    // tile. Returns the entities of the map and the unit.
+    fn world_with_unit(tile: (i32, i32, i32)) -> (World, Entity, Entity) {
+        let mut world = World::new();
+        let graph = TileGraph::new(&MapShape::Hexagon(4).tiles(), 25.0);
+        let (x, y) = graph.cube_to_world(tile);
+        let map = world.spawn().insert(graph).id();
+        let unit = world
+            .spawn()
+            .insert(Transform::from_xyz(x, y, 1.0))
+            .insert(CharState::Idle)
+            .id();
+        (world, map, unit)
+    }
+
+    fn run_occupancy(world: &mut World) {
+        SystemStage::single(occupancy_system.system()).run(world);
+    }
+
+    #[test]
+    fn occupancy_follows_a_teleport() {
+        let (mut world, map, unit) = world_with_unit((0, 0, 0));
+        run_occupancy(&mut world);
+        assert!(world.get::<TileGraph>(map).unwrap().is_occupied((0, 0, 0)));
+
+        let (x, y) = world
+            .get::<TileGraph>(map)
+            .unwrap()
+            .cube_to_world((3, -3, 0));
+        world.get_mut::<Transform>(unit).unwrap().translation = Vec3::new(x, y, 1.0);
+        run_occupancy(&mut world);
+        let graph = world.get::<TileGraph>(map).unwrap();
+        assert!(!graph.is_occupied((0, 0, 0)));
+        assert!(graph.is_occupied((3, -3, 0)));
+    }
+
+    #[test]
+    fn despawned_units_free_their_tile() {
+        let (mut world, map, unit) = world_with_unit((1, -1, 0));
+        run_occupancy(&mut world);
+        assert!(world.get::<TileGraph>(map).unwrap().is_occupied((1, -1, 0)));
+
+        world.despawn(unit);
+        run_occupancy(&mut world);
+        assert!(!world.get::<TileGraph>(map).unwrap().is_occupied((1, -1, 0)));
+    }
+}
@@ -1,39 +1,211 @@
 use crate::components::*;
 use crate::events::*;
+use crate::resources::{InputBuffer, SimulationTime, Targeting};
 use bevy::prelude::*;
 
 /// System that waits for player actions from the input system.
 /// Checks the player state to see if the action is allowed.
 /// For example, if they player is currently casting then the
 /// action is ignored.
+///
+/// Cast abilities are cast according to the `CastMode` the user picked
+/// for them. Abilities that aren't quick cast are aimed first, moving
+/// cancels aiming.
+///
+/// Queued moves and casts are added to the player's command queue
+/// instead, any other new move or cast replaces the queue. Moves and
+/// casts that are refused during a cast are buffered, see
+/// `input_buffer_system`.
 pub fn player_action_system(
+    time: Res<SimulationTime>,
     mut action_event: EventReader<PlayerAction>,
-    mut query: Query<&mut CharState, With<Player>>,
+    mut targeting: ResMut<Targeting>,
+    mut buffer: ResMut<InputBuffer>,
+    mut query: Query<(&mut CharState, &mut CommandQueue), With<Player>>,
+    q_controls: Query<&UserControls>,
 ) {
-    if let Ok(mut state) = query.single_mut() {
+    if let Ok((mut state, mut queue)) = query.single_mut() {
         for player_action in action_event.iter() {
-            match player_action.action {
-                Action::Move => {
-                    if state.can_move() {
-                        if let CharState::Moving(destination, _) = *state {
-                            // if our movement action is close to the current movement command then
-                            // don't update and use old path. This fixes a bug where if the move
-                            // command is held in one spot the paths chosen can flip back and forth
-                            // locking the player in place
-                            if (destination.0 - player_action.mouse_coords).length() < 50.0 {
-                                return;
-                            }
-                        }
-                        *state = CharState::from(*player_action)
+            match (
+                player_action.action,
+                player_action.phase,
+                player_action.queued,
+            ) {
+                (Action::Move, ActionPhase::Started, true)
+                | (Action::Cast(_), ActionPhase::Started, true) => {
+                    queue.0.push_back(*player_action);
+                    continue;
+                }
+                // holding a queued input doesn't queue it again
+                (Action::Move, ActionPhase::Held, true)
+                | (Action::Cast(_), ActionPhase::Held, true) => continue,
+                (Action::Move, ActionPhase::Started, false)
+                | (Action::Cast(_), ActionPhase::Started, false)
+                | (Action::Channel(_), ActionPhase::Started, false) => {
+                    if !queue.0.is_empty() {
+                        queue.0.clear();
                     }
                 }
-                Action::Cast(_) => {
-                    if state.can_cast() {
-                        *state = CharState::from(*player_action)
+                _ => (),
+            }
+
+            let cast_mode = |ability: CastAbility| {
+                q_controls
+                    .single()
+                    .map_or(CastMode::Quick, |controls| controls.cast_mode(ability))
+            };
+            if let Err(refused) = apply_action(player_action, &mut state, &mut targeting, cast_mode)
+            {
+                // remember moves and casts pressed during a cast so they
+                // can go through once the cast is done
+                if matches!(*state, CharState::Casting(_)) {
+                    buffer.action = Some((refused, time.seconds_since_startup()));
+                }
+            }
+        }
+    }
+}
+
+/// System that starts the next queued command of every idle unit.
+/// Units that die lose their queue.
+pub fn command_queue_system(mut query: Query<(&mut CharState, &mut CommandQueue)>) {
+    for (mut state, mut queue) in query.iter_mut() {
+        match *state {
+            CharState::Idle => {
+                if let Some(command) = queue.0.pop_front() {
+                    *state = CharState::from(command);
+                }
+            }
+            CharState::Dead => {
+                if !queue.0.is_empty() {
+                    queue.0.clear();
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// System that replays the move or cast that was buffered while the
+/// player was casting as soon as the player's state allows it. Buffered
+/// input that is older than the controls' input buffer window is dropped.
+pub fn input_buffer_system(
+    time: Res<SimulationTime>,
+    mut buffer: ResMut<InputBuffer>,
+    mut query: Query<&mut CharState, With<Player>>,
+    q_controls: Query<&UserControls>,
+) {
+    if let (Some((action, pressed_at)), Ok(mut state)) = (buffer.action, query.single_mut()) {
+        let window = q_controls
+            .single()
+            .map_or(DEFAULT_INPUT_BUFFER, |controls| controls.input_buffer);
+        if time.seconds_since_startup() - pressed_at > window as f64 {
+            buffer.action = None;
+        } else if !matches!(*state, CharState::Casting(_)) {
+            buffer.action = None;
+            let allowed = match action.action {
+                Action::Move => state.can_move(),
+                _ => state.can_cast(),
+            };
+            if allowed {
+                *state = CharState::from(action);
+            }
+        }
+    }
+}
+
+// Applies one player action to the player's state and aim. Returns
+// the move or cast that the player's state refused, resolved to a
+// cast at the coords it was aimed at.
+fn apply_action(
+    player_action: &PlayerAction,
+    state: &mut CharState,
+    targeting: &mut Targeting,
+    cast_mode: impl Fn(CastAbility) -> CastMode,
+) -> Result<(), PlayerAction> {
+    match (player_action.action, player_action.phase) {
+        (Action::Move, ActionPhase::Ended) => (),
+        (Action::Move, phase) => {
+            if phase == ActionPhase::Started {
+                targeting.ability = None;
+            }
+            if state.can_move() {
+                if let CharState::Moving(destination, _) = *state {
+                    // if a held move command stays close to the current destination
+                    // then don't update and use the old path. This fixes a bug where
+                    // if the move command is held in one spot the paths chosen can
+                    // flip back and forth locking the player in place
+                    if phase == ActionPhase::Held
+                        && (destination.0 - player_action.mouse_coords).length() < 50.0
+                    {
+                        return Ok(());
                     }
                 }
-                Action::Channel(_) => (),
+                *state = CharState::from(*player_action)
+            } else if phase == ActionPhase::Started {
+                return Err(*player_action);
             }
         }
+        (Action::Cast(ability), phase) => {
+            match (cast_mode(ability), phase) {
+                (CastMode::Quick, ActionPhase::Ended) => (),
+                (CastMode::Quick, _) => return cast(state, *player_action),
+                // aiming can start in the middle of another cast
+                (_, ActionPhase::Started) => {
+                    if *state != CharState::Dead {
+                        targeting.ability = Some(ability);
+                    }
+                }
+                (CastMode::OnRelease, ActionPhase::Ended) => {
+                    if targeting.ability == Some(ability) {
+                        targeting.ability = None;
+                        return cast(state, *player_action);
+                    }
+                }
+                _ => (),
+            }
+        }
+        (Action::Confirm, ActionPhase::Started) => {
+            if let Some(ability) = targeting.ability.take() {
+                return cast(
+                    state,
+                    PlayerAction::new(
+                        Action::Cast(ability),
+                        ActionPhase::Started,
+                        player_action.mouse_coords,
+                    ),
+                );
+            }
+        }
+        (Action::Confirm, _) => (),
+        (Action::Channel(ability), ActionPhase::Ended) => {
+            if let CharState::Channeling((channeling, _)) = *state {
+                if channeling == ability {
+                    *state = CharState::Idle
+                }
+            }
+        }
+        (Action::Channel(ability), phase) => match state {
+            // while the channel goes on it stays aimed at the latest
+            // mouse coords, whether the input is held or pressed again
+            CharState::Channeling((channeling, target)) if *channeling == ability => {
+                *target = Coords(player_action.mouse_coords);
+            }
+            _ => {
+                if phase == ActionPhase::Started && state.can_cast() {
+                    *state = CharState::from(*player_action)
+                }
+            }
+        },
+    }
+    Ok(())
+}
+
+fn cast(state: &mut CharState, action: PlayerAction) -> Result<(), PlayerAction> {
+    if state.can_cast() {
+        *state = CharState::from(action);
+        Ok(())
+    } else {
+        Err(action)
     }
 }
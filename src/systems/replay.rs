@@ -0,0 +1,55 @@
+use crate::components::{Player, UserControls};
+use crate::events::PlayerAction;
+use crate::replay::{Recorder, Replay, ReplayInput, ReplaySettings};
+use crate::resources::{GamepadInput, SimulationTime};
+use bevy::prelude::*;
+
+/// System that writes the player's actions and gamepad stick to the
+/// recording in the tick the simulation acts on them, after the
+/// player's settings.
+pub fn record_system(
+    time: Res<SimulationTime>,
+    gamepad: Res<GamepadInput>,
+    mut recorder: ResMut<Recorder>,
+    mut action_reader: EventReader<PlayerAction>,
+    q_player: Query<(), With<Player>>,
+    q_controls: Query<&UserControls>,
+) {
+    let tick = recorder.tick(&time, q_player.iter().next().is_some());
+    let actions: Vec<PlayerAction> = action_reader.iter().copied().collect();
+    // input from before the player was on the map does nothing
+    if let Some(tick) = tick {
+        let settings = || {
+            q_controls
+                .single()
+                .map_or_else(|_| ReplaySettings::default(), ReplaySettings::from)
+        };
+        if let Err(err) = recorder.record(tick, settings, &actions, gamepad.movement) {
+            error!("{}", err);
+        }
+    }
+}
+
+/// System that plays back the recorded actions and gamepad stick in
+/// the ticks they were recorded in.
+pub fn replay_system(
+    time: Res<SimulationTime>,
+    mut replay: ResMut<Replay>,
+    mut gamepad: ResMut<GamepadInput>,
+    mut action_writer: EventWriter<PlayerAction>,
+    q_player: Query<(), With<Player>>,
+) {
+    let player_spawned = q_player.iter().next().is_some();
+    for input in replay.next_inputs(&time, player_spawned) {
+        match input {
+            ReplayInput::Action(action) => action_writer.send(action),
+            ReplayInput::Stick(movement) => gamepad.movement = movement,
+        }
+    }
+}
+
+/// System that gives the player the controls of the recording, so
+/// that its settings play out the same way.
+pub fn replay_controls_setup_system(mut commands: Commands, replay: Res<Replay>) {
+    commands.spawn().insert(replay.settings().controls());
+}
@@ -1,27 +1,47 @@
 use crate::components::*;
+use crate::config;
 use crate::entities::*;
+use crate::map::{MapAsset, MapHandle};
+use crate::systems::movement::TileGraph;
 use bevy::prelude::*;
 use bevy_prototype_lyon::prelude::*;
 
-pub fn setup_system(
-    mut commands: Commands,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    asset_server: Res<AssetServer>,
-) {
+pub fn camera_setup_system(mut commands: Commands) {
     commands
         .spawn_bundle(OrthographicCameraBundle::new_2d())
         .insert(MainCamera);
     commands.spawn_bundle(UiCameraBundle::default());
-    commands.spawn().insert(UserControls::new());
-    commands.spawn_bundle(PlayerBundle::new(&asset_server, &mut materials));
-    commands.spawn_bundle(MobBundle::new(&asset_server, &mut materials));
-    commands.spawn_bundle(DashBundle::new());
+}
+
+pub fn controls_setup_system(mut commands: Commands) {
+    commands.spawn().insert(config::controls_or_default());
+}
+
+pub fn map_setup_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+    // the map and the units on it are spawned once the map file has loaded
+    commands.insert_resource(MapHandle(asset_server.load("maps/arena.map.ron")));
+}
+
+/// System that waits for the map file to finish loading and then spawns
+/// the hex cells, the `TileGraph`, the player and the mobs from it.
+pub fn spawn_map_system(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    asset_server: Res<AssetServer>,
+    maps: Res<Assets<MapAsset>>,
+    map_handle: Option<Res<MapHandle>>,
+) {
+    let map = match map_handle.and_then(|handle| maps.get(&handle.0)) {
+        Some(map) => map,
+        None => return,
+    };
+    commands.remove_resource::<MapHandle>();
+
+    let graph = TileGraph::new(&map.passable_tiles(), map.cell_size);
 
-    let map_size = 10;
-    let cell_size = 25.0;
     let cell = shapes::RegularPolygon {
         sides: 6,
-        feature: shapes::RegularPolygonFeature::Radius(cell_size),
+        feature: shapes::RegularPolygonFeature::Radius(map.cell_size),
         ..shapes::RegularPolygon::default()
     };
     let drawmode = DrawMode::Outlined {
@@ -29,46 +49,90 @@ pub fn setup_system(
         outline_options: StrokeOptions::default().with_line_width(1.0),
     };
 
-    let root3 = 3.0_f32.sqrt();
-    let q_basis = Vec2::new(3.0 / 2.0, root3 / 2.0);
-    let r_basis = Vec2::new(0.0, root3);
-    let basis_matrix = bevy::math::Mat2::from_cols(q_basis, r_basis);
-
-    // easiest way to get a full hexagon map
-    let tiles = (-map_size..=map_size)
-        .map(|x| (-map_size..=map_size).map(move |y| (x, y)))
-        .flatten()
-        .map(|(x, y)| (-map_size..=map_size).map(move |z| (x, y, z)))
-        .flatten()
-        .filter(|coords| coords.0 + coords.1 + coords.2 == 0);
-
-    for tile in tiles {
-        let color = ShapeColors {
-            main: Color::rgba(0.0, 0.0, 0.0, 0.0),
-            outline: Color::BLACK,
+    for tile in map.tiles() {
+        let impassable = map.impassable.contains(&tile);
+        let color = if impassable {
+            ShapeColors {
+                main: Color::DARK_GRAY,
+                outline: Color::BLACK,
+            }
+        } else {
+            ShapeColors {
+                main: Color::rgba(0.0, 0.0, 0.0, 0.0),
+                outline: Color::BLACK,
+            }
         };
 
-        let (q, r) = cube_to_axial(tile);
-        let position = cell_size * basis_matrix * Vec2::new(q as f32, r as f32);
+        let (x, y) = graph.cube_to_world(tile);
+        let mut cell_entity = commands.spawn_bundle(GeometryBuilder::build_as(
+            &cell,
+            color,
+            drawmode,
+            Transform::from_xyz(x, y, 0.0),
+        ));
+        cell_entity.insert(Cell);
+        if impassable {
+            cell_entity.insert(Impassable);
+        }
+    }
+
+    let (x, y) = graph.cube_to_world(map.player_spawn);
+    let player = commands
+        .spawn_bundle(PlayerBundle::new(
+            &asset_server,
+            &mut materials,
+            Vec3::new(x, y, 1.0),
+        ))
+        .id();
+    spawn_loadout(&mut commands, &asset_server, player, PLAYER_LOADOUT);
+    commands.spawn_bundle(LazerBundle::new(
+        player,
+        asset_server.load("abilities/lazer.channel.ron"),
+    ));
 
-        commands
-            .spawn_bundle(GeometryBuilder::build_as(
-                &cell,
-                color,
-                drawmode,
-                Transform::from_xyz(position.x, position.y, 0.0),
+    for spawn in map.mob_spawns.iter() {
+        let (x, y) = graph.cube_to_world(*spawn);
+        let mob = commands
+            .spawn_bundle(MobBundle::new(
+                &asset_server,
+                &mut materials,
+                Vec3::new(x, y, 1.0),
             ))
-            .insert(Cell);
+            .id();
+        spawn_loadout(&mut commands, &asset_server, mob, MOB_LOADOUT);
     }
 
-    let graph = crate::systems::movement::TileGraph::new(map_size, cell_size);
     commands.spawn().insert(graph);
+}
 
-    ui(commands, materials, asset_server);
+// Cast abilities each kind of unit spawns with and the files they
+// are defined in.
+const PLAYER_LOADOUT: &[(CastAbility, &str)] = &[
+    (CastAbility::Dash, "abilities/dash.ability.ron"),
+    (CastAbility::Shoot, "abilities/shoot.ability.ron"),
+];
+const MOB_LOADOUT: &[(CastAbility, &str)] =
+    &[(CastAbility::Shoot, "abilities/mob_shot.ability.ron")];
+
+// Spawns the abilities in `loadout` for `owner`, every unit gets its
+// own charges and cooldowns.
+fn spawn_loadout(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    owner: Entity,
+    loadout: &[(CastAbility, &str)],
+) {
+    for (ability, path) in loadout.iter() {
+        commands.spawn_bundle(AbilityBundle::new(
+            owner,
+            *ability,
+            asset_server.load(*path),
+        ));
+    }
 }
 
 /// this ui is scary right now
-fn ui(
+pub fn ui_setup_system(
     mut commands: Commands,
     mut materials: ResMut<Assets<ColorMaterial>>,
 
@@ -145,19 +209,21 @@ fn ui(
                                         })
                                         .with_children(|parent| {
                                             // Health bar red fill
-                                            parent.spawn_bundle(NodeBundle {
-                                                style: Style {
-                                                    size: Size::new(
-                                                        Val::Percent(100.0),
-                                                        Val::Percent(100.0),
-                                                    ),
-                                                    align_items: AlignItems::FlexStart,
-                                                    flex_direction: FlexDirection::Row,
+                                            parent
+                                                .spawn_bundle(NodeBundle {
+                                                    style: Style {
+                                                        size: Size::new(
+                                                            Val::Percent(100.0),
+                                                            Val::Percent(100.0),
+                                                        ),
+                                                        align_items: AlignItems::FlexStart,
+                                                        flex_direction: FlexDirection::Row,
+                                                        ..Default::default()
+                                                    },
+                                                    material: materials.add(Color::RED.into()),
                                                     ..Default::default()
-                                                },
-                                                material: materials.add(Color::RED.into()),
-                                                ..Default::default()
-                                            });
+                                                })
+                                                .insert(HealthBar);
                                         });
                                     // Health number area
                                     parent
@@ -178,23 +244,25 @@ fn ui(
                                         })
                                         .with_children(|parent| {
                                             // hp text
-                                            parent.spawn_bundle(TextBundle {
-                                                style: Style {
-                                                    margin: Rect::all(Val::Px(5.0)),
-                                                    ..Default::default()
-                                                },
-                                                text: Text::with_section(
-                                                    "100 / 100",
-                                                    TextStyle {
-                                                        font: asset_server
-                                                            .load("fonts/FiraSans-Bold.ttf"),
-                                                        font_size: 30.0,
-                                                        color: Color::WHITE,
+                                            parent
+                                                .spawn_bundle(TextBundle {
+                                                    style: Style {
+                                                        margin: Rect::all(Val::Px(5.0)),
+                                                        ..Default::default()
                                                     },
-                                                    Default::default(),
-                                                ),
-                                                ..Default::default()
-                                            });
+                                                    text: Text::with_section(
+                                                        "100 / 100",
+                                                        TextStyle {
+                                                            font: asset_server
+                                                                .load("fonts/FiraSans-Bold.ttf"),
+                                                            font_size: 30.0,
+                                                            color: Color::WHITE,
+                                                        },
+                                                        Default::default(),
+                                                    ),
+                                                    ..Default::default()
+                                                })
+                                                .insert(HealthText);
                                         });
                                 });
                             // energy area
@@ -229,19 +297,21 @@ fn ui(
                                         })
                                         .with_children(|parent| {
                                             // Energy bar gold fill
-                                            parent.spawn_bundle(NodeBundle {
-                                                style: Style {
-                                                    size: Size::new(
-                                                        Val::Percent(100.0),
-                                                        Val::Percent(100.0),
-                                                    ),
-                                                    align_items: AlignItems::FlexStart,
-                                                    flex_direction: FlexDirection::Row,
+                                            parent
+                                                .spawn_bundle(NodeBundle {
+                                                    style: Style {
+                                                        size: Size::new(
+                                                            Val::Percent(100.0),
+                                                            Val::Percent(100.0),
+                                                        ),
+                                                        align_items: AlignItems::FlexStart,
+                                                        flex_direction: FlexDirection::Row,
+                                                        ..Default::default()
+                                                    },
+                                                    material: materials.add(Color::GOLD.into()),
                                                     ..Default::default()
-                                                },
-                                                material: materials.add(Color::GOLD.into()),
-                                                ..Default::default()
-                                            });
+                                                })
+                                                .insert(EnergyBar);
                                         });
                                     // Energy number area
                                     parent
@@ -262,23 +332,25 @@ fn ui(
                                         })
                                         .with_children(|parent| {
                                             // energy text
-                                            parent.spawn_bundle(TextBundle {
-                                                style: Style {
-                                                    margin: Rect::all(Val::Px(5.0)),
-                                                    ..Default::default()
-                                                },
-                                                text: Text::with_section(
-                                                    "100 / 100",
-                                                    TextStyle {
-                                                        font: asset_server
-                                                            .load("fonts/FiraSans-Bold.ttf"),
-                                                        font_size: 30.0,
-                                                        color: Color::WHITE,
+                                            parent
+                                                .spawn_bundle(TextBundle {
+                                                    style: Style {
+                                                        margin: Rect::all(Val::Px(5.0)),
+                                                        ..Default::default()
                                                     },
-                                                    Default::default(),
-                                                ),
-                                                ..Default::default()
-                                            });
+                                                    text: Text::with_section(
+                                                        "100 / 100",
+                                                        TextStyle {
+                                                            font: asset_server
+                                                                .load("fonts/FiraSans-Bold.ttf"),
+                                                            font_size: 30.0,
+                                                            color: Color::WHITE,
+                                                        },
+                                                        Default::default(),
+                                                    ),
+                                                    ..Default::default()
+                                                })
+                                                .insert(EnergyText);
                                         });
                                 });
                         });
@@ -310,24 +382,53 @@ fn ui(
                                     ..Default::default()
                                 })
                                 .with_children(|parent| {
-                                    for _ in 0..5 {
+                                    let slots = [
+                                        Some(CastAbility::Dash),
+                                        Some(CastAbility::Shoot),
+                                        None,
+                                        None,
+                                        None,
+                                    ];
+                                    for slot in slots.iter() {
                                         let val = 0.55;
-                                        parent.spawn_bundle(NodeBundle {
-                                            style: Style {
-                                                size: Size::new(
-                                                    Val::Percent(25.0),
-                                                    Val::Percent(100.0),
-                                                ),
-                                                padding: Rect::all(Val::Px(3.0)),
-                                                align_items: AlignItems::FlexStart,
-                                                flex_direction: FlexDirection::Row,
-                                                aspect_ratio: Some(1.0),
+                                        parent
+                                            .spawn_bundle(NodeBundle {
+                                                style: Style {
+                                                    size: Size::new(
+                                                        Val::Percent(25.0),
+                                                        Val::Percent(100.0),
+                                                    ),
+                                                    padding: Rect::all(Val::Px(3.0)),
+                                                    align_items: AlignItems::FlexStart,
+                                                    flex_direction: FlexDirection::Row,
+                                                    aspect_ratio: Some(1.0),
+                                                    ..Default::default()
+                                                },
+                                                material: materials
+                                                    .add(Color::rgb(val, val, val).into()),
                                                 ..Default::default()
-                                            },
-                                            material: materials
-                                                .add(Color::rgb(val, val, val).into()),
-                                            ..Default::default()
-                                        });
+                                            })
+                                            .with_children(|parent| {
+                                                // cooldown sweep, starts empty
+                                                if let Some(ability) = slot {
+                                                    parent
+                                                        .spawn_bundle(NodeBundle {
+                                                            style: Style {
+                                                                size: Size::new(
+                                                                    Val::Percent(100.0),
+                                                                    Val::Percent(0.0),
+                                                                ),
+                                                                ..Default::default()
+                                                            },
+                                                            material: materials.add(
+                                                                Color::rgba(0.0, 0.0, 0.0, 0.6)
+                                                                    .into(),
+                                                            ),
+                                                            ..Default::default()
+                                                        })
+                                                        .insert(CooldownSweep(*ability));
+                                                }
+                                            });
                                     }
                                 });
 
@@ -346,34 +447,28 @@ fn ui(
                                     ..Default::default()
                                 })
                                 .with_children(|parent| {
-                                    for _ in 0..3 {
+                                    for index in 0..3 {
                                         let val = 0.55;
-                                        parent.spawn_bundle(NodeBundle {
-                                            style: Style {
-                                                size: Size::new(
-                                                    Val::Percent(25.0),
-                                                    Val::Percent(100.0),
-                                                ),
-                                                padding: Rect::all(Val::Px(3.0)),
-                                                align_items: AlignItems::FlexStart,
-                                                flex_direction: FlexDirection::Row,
+                                        parent
+                                            .spawn_bundle(NodeBundle {
+                                                style: Style {
+                                                    size: Size::new(
+                                                        Val::Percent(25.0),
+                                                        Val::Percent(100.0),
+                                                    ),
+                                                    padding: Rect::all(Val::Px(3.0)),
+                                                    align_items: AlignItems::FlexStart,
+                                                    flex_direction: FlexDirection::Row,
+                                                    ..Default::default()
+                                                },
+                                                material: materials
+                                                    .add(Color::rgb(val, val, val).into()),
                                                 ..Default::default()
-                                            },
-                                            material: materials
-                                                .add(Color::rgb(val, val, val).into()),
-                                            ..Default::default()
-                                        });
+                                            })
+                                            .insert(ChargeBattery(index));
                                     }
                                 });
                         });
                 });
         });
 }
-
-fn cube_to_axial(coords: (i32, i32, i32)) -> (i32, i32) {
-    (coords.0, coords.2)
-}
-
-fn axial_to_cube(coords: (i32, i32)) -> (i32, i32, i32) {
-    (coords.0, -coords.0 - coords.1, coords.1)
-}
@@ -0,0 +1,73 @@
+use crate::components::*;
+use crate::entities::{RangeIndicatorBundle, WaypointMarkerBundle};
+use crate::resources::Targeting;
+use crate::systems::movement::TileGraph;
+use bevy::prelude::*;
+
+/// System that draws the range of the ability the player is aiming
+/// around the player. Aiming is cancelled if the player dies.
+pub fn range_indicator_system(
+    mut commands: Commands,
+    mut targeting: ResMut<Targeting>,
+    player_query: Query<(Entity, &Transform, &CharState), With<Player>>,
+    ability_query: Query<(&AbilityOwner, &CastAbility, &CastRange)>,
+    mut indicator_query: Query<(Entity, &RangeIndicator, &mut Transform), Without<Player>>,
+) {
+    let (player, player_transform, state) = match player_query.single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+
+    if *state == CharState::Dead {
+        targeting.ability = None;
+    }
+
+    let mut drawn = false;
+    for (indicator, range_indicator, mut transform) in indicator_query.iter_mut() {
+        if targeting.ability == Some(range_indicator.0) {
+            transform.translation.x = player_transform.translation.x;
+            transform.translation.y = player_transform.translation.y;
+            drawn = true;
+        } else {
+            commands.entity(indicator).despawn();
+        }
+    }
+
+    if let (Some(ability), false) = (targeting.ability, drawn) {
+        let found = ability_query
+            .iter()
+            .find(|(owner, class, _)| owner.0 == player && **class == ability);
+        if let Some((_, _, range)) = found {
+            commands.spawn_bundle(RangeIndicatorBundle::new(
+                ability,
+                range.0,
+                player_transform.translation,
+            ));
+        }
+    }
+}
+
+/// System that marks the tile targeted by each command in the
+/// player's command queue. Markers are redrawn when the queue changes.
+pub fn waypoint_marker_system(
+    mut commands: Commands,
+    q_graph: Query<&TileGraph>,
+    queue_query: Query<&CommandQueue, (With<Player>, Changed<CommandQueue>)>,
+    marker_query: Query<Entity, With<WaypointMarker>>,
+) {
+    if let (Ok(queue), Ok(graph)) = (queue_query.single(), q_graph.single()) {
+        for marker in marker_query.iter() {
+            commands.entity(marker).despawn();
+        }
+
+        for command in queue.0.iter() {
+            let tile = graph.world_to_cube((command.mouse_coords.x, command.mouse_coords.y));
+            let (x, y) = graph.cube_to_world(tile);
+            commands.spawn_bundle(WaypointMarkerBundle::new(
+                command.action,
+                graph.cell_size(),
+                Vec3::new(x, y, 0.0),
+            ));
+        }
+    }
+}
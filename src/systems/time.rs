@@ -0,0 +1,19 @@
+use crate::resources::SimulationTime;
+use bevy::ecs::schedule::ShouldRun;
+use bevy::prelude::*;
+
+/// System that adds the frame time to the simulation clock at the
+/// start of every frame.
+pub fn simulation_time_system(time: Res<Time>, mut sim_time: ResMut<SimulationTime>) {
+    sim_time.add_frame(time.delta());
+}
+
+/// Run criteria of the `SimulationStage` that runs it once for every
+/// tick that is due.
+pub fn simulation_tick_criteria(mut sim_time: ResMut<SimulationTime>) -> ShouldRun {
+    if sim_time.next_tick() {
+        ShouldRun::YesAndCheckAgain
+    } else {
+        ShouldRun::No
+    }
+}
@@ -0,0 +1,630 @@
+use bevy::prelude::*;
+use rust_game::ability::{AbilityDef, EffectDef};
+use rust_game::components::*;
+use rust_game::entities::{AbilityBundle, MobBundle, PlayerBundle};
+use rust_game::events::*;
+use rust_game::map::MapShape;
+use rust_game::plugins::{GameplayPlugin, RecordPlugin, ReplayPlugin};
+use rust_game::replay::{RecordedInput, Recorder, Replay, ReplayInput, ReplaySettings};
+use rust_game::resources::{GamepadInput, SimulationTime, Targeting};
+use rust_game::systems::effects;
+use rust_game::systems::movement::TileGraph;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::time::Duration;
+
+const CELL_SIZE: f32 = 25.0;
+// the mob is straight east of the player, out of its aggro radius
+const MOB_X: f32 = 450.0;
+
+struct Arena {
+    player: Entity,
+    mob: Entity,
+    shoot: Handle<AbilityDef>,
+}
+
+fn ability(cast_time: f32, range: f32, effect: &str, params: &[(&str, f32)]) -> AbilityDef {
+    AbilityDef {
+        cast_time,
+        cooldown: 3.0,
+        charges: 3,
+        range,
+        energy_cost: 0,
+        move_after_cast: false,
+        sound: None,
+        effects: vec![EffectDef {
+            name: effect.to_string(),
+            params: params
+                .iter()
+                .map(|(name, value)| (name.to_string(), *value))
+                .collect::<FxHashMap<_, _>>(),
+        }],
+    }
+}
+
+// Player in the middle of an open map with a dash and a shot, and
+// one mob to shoot at.
+fn spawn_arena(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut defs: ResMut<Assets<AbilityDef>>,
+) {
+    let player = commands
+        .spawn_bundle(PlayerBundle::new(
+            &asset_server,
+            &mut materials,
+            Vec3::new(0.0, 0.0, 1.0),
+        ))
+        .id();
+    let mob = commands
+        .spawn_bundle(MobBundle::new(
+            &asset_server,
+            &mut materials,
+            Vec3::new(MOB_X, 0.0, 1.0),
+        ))
+        .id();
+
+    let dash = defs.add(ability(0.1, 400.0, effects::TELEPORT, &[]));
+    let shoot = defs.add(ability(
+        0.15,
+        600.0,
+        effects::PROJECTILE,
+        &[("speed", 800.0), ("range", 600.0), ("damage", 25.0)],
+    ));
+    commands.spawn_bundle(AbilityBundle::new(player, CastAbility::Dash, dash));
+    commands.spawn_bundle(AbilityBundle::new(
+        player,
+        CastAbility::Shoot,
+        shoot.clone(),
+    ));
+
+    commands
+        .spawn()
+        .insert(TileGraph::new(&MapShape::Hexagon(14).tiles(), CELL_SIZE));
+    commands.insert_resource(Arena { player, mob, shoot });
+}
+
+fn tick_length() -> Duration {
+    Duration::from_secs_f64(1.0 / 60.0)
+}
+
+// Headless app that runs one tick of a 60th of a second per update,
+// with the arena spawned and the ability definitions applied.
+fn app() -> App {
+    app_with(SimulationTime::stepped(tick_length()), |_| {})
+}
+
+fn app_with(time: SimulationTime, setup: impl FnOnce(&mut AppBuilder)) -> App {
+    let mut builder = App::build();
+    builder
+        .insert_resource(time)
+        .add_plugins(MinimalPlugins)
+        .add_plugin(bevy::asset::AssetPlugin::default())
+        .add_asset::<ColorMaterial>()
+        .add_plugin(GameplayPlugin)
+        .add_startup_system(spawn_arena.system());
+    setup(&mut builder);
+    let mut app = builder.app;
+    step(&mut app, 2);
+    app
+}
+
+fn step(app: &mut App, frames: usize) {
+    for _ in 0..frames {
+        app.update();
+    }
+}
+
+fn arena(app: &App) -> (Entity, Entity) {
+    let arena = app.world.get_resource::<Arena>().unwrap();
+    (arena.player, arena.mob)
+}
+
+fn send(app: &mut App, action: Action, target: Vec3) {
+    send_phase(app, action, ActionPhase::Started, target);
+}
+
+fn send_phase(app: &mut App, action: Action, phase: ActionPhase, target: Vec3) {
+    app.world
+        .get_resource_mut::<Events<PlayerAction>>()
+        .unwrap()
+        .send(PlayerAction::new(action, phase, target));
+}
+
+// Gives the player controls that cast the dash with `mode`.
+fn dash_cast_mode(app: &mut App, mode: CastMode) {
+    let mut controls = UserControls::new();
+    controls.cast_modes.insert(CastAbility::Dash, mode);
+    app.world.spawn().insert(controls);
+}
+
+fn aimed(app: &App) -> Option<CastAbility> {
+    app.world.get_resource::<Targeting>().unwrap().ability
+}
+
+fn position(app: &App, unit: Entity) -> Vec3 {
+    app.world.get::<Transform>(unit).unwrap().translation
+}
+
+fn charges(app: &mut App, player: Entity, ability: CastAbility) -> i64 {
+    app.world
+        .query::<(&AbilityOwner, &CastAbility, &Charges)>()
+        .iter(&app.world)
+        .find(|(owner, class, _)| owner.0 == player && **class == ability)
+        .map(|(_, _, charges)| charges.0)
+        .unwrap()
+}
+
+#[test]
+fn move_walks_the_player_to_the_target_tile() {
+    let mut app = app();
+    let (player, _) = arena(&app);
+    let target = Vec3::new(150.0, 0.0, 1.0);
+
+    send(&mut app, Action::Move, target);
+    step(&mut app, 60);
+
+    let graph = TileGraph::new(&MapShape::Hexagon(14).tiles(), CELL_SIZE);
+    let end = position(&app, player);
+    assert_eq!(
+        graph.world_to_cube((end.x, end.y)),
+        graph.world_to_cube((target.x, target.y))
+    );
+    assert!(*app.world.get::<CharState>(player).unwrap() == CharState::Idle);
+}
+
+#[test]
+fn dash_uses_a_charge_and_teleports_the_player() {
+    let mut app = app();
+    let (player, _) = arena(&app);
+    assert_eq!(charges(&mut app, player, CastAbility::Dash), 3);
+
+    send(
+        &mut app,
+        Action::Cast(CastAbility::Dash),
+        Vec3::new(300.0, 0.0, 1.0),
+    );
+    step(&mut app, 15);
+
+    assert_eq!(charges(&mut app, player, CastAbility::Dash), 2);
+    assert!(position(&app, player).distance(Vec3::new(300.0, 0.0, 1.0)) < 1.0);
+    assert!(*app.world.get::<CharState>(player).unwrap() == CharState::Idle);
+}
+
+#[test]
+fn first_dash_takes_its_whole_cast_time() {
+    let mut app = app();
+    let (player, _) = arena(&app);
+
+    send(
+        &mut app,
+        Action::Cast(CastAbility::Dash),
+        Vec3::new(300.0, 0.0, 1.0),
+    );
+    // well short of the dash's 0.1 second cast time
+    step(&mut app, 3);
+    assert!(matches!(
+        *app.world.get::<CharState>(player).unwrap(),
+        CharState::Casting((CastAbility::Dash, _))
+    ));
+
+    step(&mut app, 12);
+    assert!(position(&app, player).distance(Vec3::new(300.0, 0.0, 1.0)) < 1.0);
+}
+
+#[test]
+fn last_charge_still_teleports_the_player() {
+    let mut app = app();
+    let (player, _) = arena(&app);
+
+    for x in [-100.0, -200.0, -300.0].iter() {
+        send(
+            &mut app,
+            Action::Cast(CastAbility::Dash),
+            Vec3::new(*x, 0.0, 1.0),
+        );
+        step(&mut app, 15);
+        assert!(position(&app, player).distance(Vec3::new(*x, 0.0, 1.0)) < 1.0);
+    }
+    assert_eq!(charges(&mut app, player, CastAbility::Dash), 0);
+
+    // without a charge the dash fizzles instead of going off for free
+    send(
+        &mut app,
+        Action::Cast(CastAbility::Dash),
+        Vec3::new(-400.0, 0.0, 1.0),
+    );
+    step(&mut app, 15);
+    assert!(position(&app, player).distance(Vec3::new(-300.0, 0.0, 1.0)) < 1.0);
+    assert_eq!(charges(&mut app, player, CastAbility::Dash), 0);
+}
+
+#[test]
+fn normal_cast_waits_for_a_confirmed_target() {
+    let mut app = app();
+    let (player, _) = arena(&app);
+    dash_cast_mode(&mut app, CastMode::Normal);
+
+    send(
+        &mut app,
+        Action::Cast(CastAbility::Dash),
+        Vec3::new(100.0, 0.0, 1.0),
+    );
+    step(&mut app, 15);
+    assert_eq!(charges(&mut app, player, CastAbility::Dash), 3);
+    assert_eq!(aimed(&app), Some(CastAbility::Dash));
+
+    send(&mut app, Action::Confirm, Vec3::new(300.0, 0.0, 1.0));
+    step(&mut app, 15);
+    assert_eq!(charges(&mut app, player, CastAbility::Dash), 2);
+    assert_eq!(aimed(&app), None);
+    assert!(position(&app, player).distance(Vec3::new(300.0, 0.0, 1.0)) < 1.0);
+}
+
+#[test]
+fn on_release_cast_goes_off_when_the_input_is_released() {
+    let mut app = app();
+    let (player, _) = arena(&app);
+    dash_cast_mode(&mut app, CastMode::OnRelease);
+
+    send(
+        &mut app,
+        Action::Cast(CastAbility::Dash),
+        Vec3::new(100.0, 0.0, 1.0),
+    );
+    step(&mut app, 15);
+    assert_eq!(charges(&mut app, player, CastAbility::Dash), 3);
+    assert_eq!(aimed(&app), Some(CastAbility::Dash));
+
+    send_phase(
+        &mut app,
+        Action::Cast(CastAbility::Dash),
+        ActionPhase::Ended,
+        Vec3::new(300.0, 0.0, 1.0),
+    );
+    step(&mut app, 15);
+    assert_eq!(charges(&mut app, player, CastAbility::Dash), 2);
+    assert_eq!(aimed(&app), None);
+    assert!(position(&app, player).distance(Vec3::new(300.0, 0.0, 1.0)) < 1.0);
+}
+
+#[test]
+fn move_sent_during_a_cast_runs_when_the_cast_ends() {
+    let mut app = app();
+    let (player, _) = arena(&app);
+    let target = Vec3::new(-300.0, 100.0, 1.0);
+
+    send(
+        &mut app,
+        Action::Cast(CastAbility::Dash),
+        Vec3::new(-300.0, 0.0, 1.0),
+    );
+    step(&mut app, 2);
+    send(&mut app, Action::Move, target);
+    step(&mut app, 60);
+
+    let graph = TileGraph::new(&MapShape::Hexagon(14).tiles(), CELL_SIZE);
+    let end = position(&app, player);
+    assert_eq!(charges(&mut app, player, CastAbility::Dash), 2);
+    assert_eq!(
+        graph.world_to_cube((end.x, end.y)),
+        graph.world_to_cube((target.x, target.y))
+    );
+}
+
+#[test]
+fn move_older_than_the_input_buffer_is_dropped() {
+    let mut app = app();
+    let (player, _) = arena(&app);
+    // much shorter than what is left of the dash's cast time
+    let mut controls = UserControls::new();
+    controls.input_buffer = 0.02;
+    app.world.spawn().insert(controls);
+
+    send(
+        &mut app,
+        Action::Cast(CastAbility::Dash),
+        Vec3::new(-300.0, 0.0, 1.0),
+    );
+    step(&mut app, 1);
+    send(&mut app, Action::Move, Vec3::new(-300.0, 100.0, 1.0));
+    step(&mut app, 30);
+
+    assert!(position(&app, player).distance(Vec3::new(-300.0, 0.0, 1.0)) < 1.0);
+    assert!(*app.world.get::<CharState>(player).unwrap() == CharState::Idle);
+}
+
+#[test]
+fn shot_damages_the_mob_it_hits() {
+    let mut app = app();
+    let (player, mob) = arena(&app);
+
+    send(
+        &mut app,
+        Action::Cast(CastAbility::Shoot),
+        Vec3::new(MOB_X, 0.0, 1.0),
+    );
+    step(&mut app, 60);
+
+    assert_eq!(charges(&mut app, player, CastAbility::Shoot), 2);
+    assert_eq!(app.world.get::<Health>(mob).unwrap().0, 75);
+    assert_eq!(app.world.get::<Health>(player).unwrap().0, 100);
+}
+
+#[test]
+fn loadout_spawned_after_its_definition_loaded_is_usable() {
+    let mut app = app();
+    let (_, mob) = arena(&app);
+    let shoot = app.world.get_resource::<Arena>().unwrap().shoot.clone();
+
+    app.world
+        .spawn()
+        .insert_bundle(AbilityBundle::new(mob, CastAbility::Shoot, shoot));
+    step(&mut app, 1);
+    assert_eq!(charges(&mut app, mob, CastAbility::Shoot), 3);
+
+    // walk into the mob's aggro radius so it shoots at the player
+    send(&mut app, Action::Move, Vec3::new(MOB_X - 250.0, 0.0, 1.0));
+    step(&mut app, 90);
+    assert!(charges(&mut app, mob, CastAbility::Shoot) < 3);
+}
+
+#[test]
+fn shot_at_the_caster_flies_off_and_despawns() {
+    let mut app = app();
+    let (player, _) = arena(&app);
+
+    send(
+        &mut app,
+        Action::Cast(CastAbility::Shoot),
+        position(&app, player),
+    );
+    step(&mut app, 15);
+    let projectiles = |app: &mut App| {
+        app.world
+            .query_filtered::<(), With<Projectile>>()
+            .iter(&app.world)
+            .count()
+    };
+    assert_eq!(projectiles(&mut app), 1);
+
+    // long enough to fly its whole range
+    step(&mut app, 60);
+    assert_eq!(projectiles(&mut app), 0);
+}
+
+#[test]
+fn mob_fires_its_only_shot_charge() {
+    let mut app = app();
+    let (player, mob) = arena(&app);
+    let def: AbilityDef =
+        ron::de::from_str(include_str!("../assets/abilities/mob_shot.ability.ron")).unwrap();
+    let shot = app
+        .world
+        .get_resource_mut::<Assets<AbilityDef>>()
+        .unwrap()
+        .add(def);
+    app.world
+        .spawn()
+        .insert_bundle(AbilityBundle::new(mob, CastAbility::Shoot, shot));
+    // so that only the shot changes the mob's energy
+    app.world.get_mut::<EnergyRegen>(mob).unwrap().amount = 0;
+
+    // walk into the mob's aggro radius so it shoots at the player, the
+    // charge doesn't come back within these frames
+    send(&mut app, Action::Move, Vec3::new(MOB_X - 250.0, 0.0, 1.0));
+    let mut projectiles = FxHashSet::default();
+    for _ in 0..120 {
+        step(&mut app, 1);
+        for (projectile, owner) in app
+            .world
+            .query_filtered::<(Entity, &AbilityOwner), With<Projectile>>()
+            .iter(&app.world)
+        {
+            if owner.0 == mob {
+                projectiles.insert(projectile);
+            }
+        }
+    }
+
+    assert_eq!(projectiles.len(), 1);
+    assert_eq!(charges(&mut app, mob, CastAbility::Shoot), 0);
+    assert_eq!(app.world.get::<Energy>(mob).unwrap().0, 95);
+    assert!(app.world.get::<Health>(player).unwrap().0 < 100);
+}
+
+#[test]
+fn move_is_acted_on_in_the_frame_it_is_issued() {
+    let mut app = app();
+    let (player, _) = arena(&app);
+    let start = position(&app, player);
+
+    send(&mut app, Action::Move, Vec3::new(150.0, 0.0, 1.0));
+    step(&mut app, 1);
+
+    // the movement system already found a path and took the first step
+    assert!(matches!(
+        *app.world.get::<CharState>(player).unwrap(),
+        CharState::Moving(_, Some(_))
+    ));
+    assert!(position(&app, player) != start);
+}
+
+#[test]
+fn cast_is_started_in_the_frame_it_is_issued() {
+    let mut app = app();
+    let (player, _) = arena(&app);
+
+    send(
+        &mut app,
+        Action::Cast(CastAbility::Dash),
+        Vec3::new(300.0, 0.0, 1.0),
+    );
+    step(&mut app, 1);
+
+    assert!(matches!(
+        *app.world.get::<CharState>(player).unwrap(),
+        CharState::Casting((CastAbility::Dash, _))
+    ));
+    assert_eq!(charges(&mut app, player, CastAbility::Dash), 2);
+}
+
+// Frames that `play_script` runs for.
+const SCRIPT_FRAMES: usize = 120;
+
+// Walks, dashes next to the mob and shoots it.
+fn play_script(app: &mut App) {
+    send(app, Action::Move, Vec3::new(-150.0, 100.0, 1.0));
+    step(app, 40);
+    send(
+        app,
+        Action::Cast(CastAbility::Dash),
+        Vec3::new(200.0, -50.0, 1.0),
+    );
+    step(app, 20);
+    send(
+        app,
+        Action::Cast(CastAbility::Shoot),
+        Vec3::new(MOB_X, 0.0, 1.0),
+    );
+    step(app, 60);
+}
+
+// Where the player and the mob are and the mob's health.
+fn outcome(app: &App) -> (Vec3, Vec3, i64) {
+    let (player, mob) = arena(app);
+    (
+        position(app, player),
+        position(app, mob),
+        app.world.get::<Health>(mob).unwrap().0,
+    )
+}
+
+#[test]
+fn same_input_plays_out_the_same_way() {
+    let mut first = app();
+    play_script(&mut first);
+    let mut second = app();
+    play_script(&mut second);
+    assert_eq!(outcome(&first), outcome(&second));
+}
+
+#[test]
+fn replay_plays_out_like_the_recording() {
+    let path = std::env::temp_dir().join(format!("rust_game_replay_{}.ron", std::process::id()));
+    let recorder = Recorder::create(&path).unwrap();
+    let mut recorded = app_with(SimulationTime::stepped(tick_length()), |builder| {
+        builder.insert_resource(recorder).add_plugin(RecordPlugin);
+    });
+    play_script(&mut recorded);
+
+    let replay = Replay::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mut replayed = app_with(SimulationTime::stepped(tick_length()), |builder| {
+        builder.insert_resource(replay).add_plugin(ReplayPlugin);
+    });
+    step(&mut replayed, SCRIPT_FRAMES);
+
+    assert!(replayed
+        .world
+        .get_resource::<Replay>()
+        .unwrap()
+        .is_finished());
+    assert_eq!(outcome(&replayed), outcome(&recorded));
+}
+
+#[test]
+fn replay_uses_the_recorded_settings_and_stick() {
+    let path = std::env::temp_dir().join(format!(
+        "rust_game_replay_settings_{}.ron",
+        std::process::id()
+    ));
+    let recorder = Recorder::create(&path).unwrap();
+    let mut controls = UserControls::new();
+    controls
+        .cast_modes
+        .insert(CastAbility::Dash, CastMode::Normal);
+    let mut recorded = app_with(SimulationTime::stepped(tick_length()), |builder| {
+        builder.world_mut().spawn().insert(controls);
+        builder.insert_resource(recorder).add_plugin(RecordPlugin);
+    });
+    // the dash only goes to the confirmed target with the recorded cast mode
+    send(
+        &mut recorded,
+        Action::Cast(CastAbility::Dash),
+        Vec3::new(100.0, 0.0, 1.0),
+    );
+    step(&mut recorded, 15);
+    send(&mut recorded, Action::Confirm, Vec3::new(300.0, 0.0, 1.0));
+    step(&mut recorded, 15);
+    let set_stick = |app: &mut App, movement: Vec2| {
+        app.world
+            .get_resource_mut::<GamepadInput>()
+            .unwrap()
+            .movement = movement;
+    };
+    set_stick(&mut recorded, Vec2::new(0.0, 1.0));
+    step(&mut recorded, 20);
+    set_stick(&mut recorded, Vec2::ZERO);
+    step(&mut recorded, 20);
+
+    let replay = Replay::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mut replayed = app_with(SimulationTime::stepped(tick_length()), |builder| {
+        builder.insert_resource(replay).add_plugin(ReplayPlugin);
+    });
+    step(&mut replayed, 70);
+
+    let (player, _) = arena(&recorded);
+    assert!(position(&recorded, player).y > 1.0);
+    assert!(replayed
+        .world
+        .get_resource::<Replay>()
+        .unwrap()
+        .is_finished());
+    assert_eq!(outcome(&replayed), outcome(&recorded));
+}
+
+// Tick that `play_at_frame_length` stops at, frames of both lengths it
+// is used with add up to exactly this many ticks.
+const END_TICK: u64 = 180;
+
+// Plays the same script as `play_script`, at fixed ticks, with frames
+// of `frame` length adding up to the ticks, and returns the outcome at
+// `END_TICK`.
+fn play_at_frame_length(frame: Duration) -> (Vec3, Vec3, i64) {
+    let at = |tick, action, x, y| RecordedInput {
+        tick,
+        input: ReplayInput::Action(PlayerAction::new(
+            action,
+            ActionPhase::Started,
+            Vec3::new(x, y, 1.0),
+        )),
+    };
+    let script = vec![
+        at(10, Action::Move, -150.0, 100.0),
+        at(50, Action::Cast(CastAbility::Dash), 200.0, -50.0),
+        at(70, Action::Cast(CastAbility::Shoot), MOB_X, 0.0),
+    ];
+    let time = SimulationTime::with_frame(tick_length(), frame);
+    let mut app = app_with(time, |builder| {
+        builder
+            .insert_resource(Replay::new(ReplaySettings::default(), script))
+            .add_plugin(ReplayPlugin);
+    });
+
+    let tick = |app: &App| app.world.get_resource::<SimulationTime>().unwrap().tick();
+    while tick(&app) < END_TICK {
+        app.update();
+    }
+    assert_eq!(tick(&app), END_TICK);
+    outcome(&app)
+}
+
+#[test]
+fn same_input_plays_out_the_same_way_at_any_frame_rate() {
+    // about 30 and 144 frames per second
+    let slow = play_at_frame_length(tick_length() * 2);
+    let fast = play_at_frame_length(tick_length() * 5 / 12);
+    assert_eq!(slow, fast);
+}